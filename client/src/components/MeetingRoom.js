@@ -1,8 +1,15 @@
 import React, { useEffect, useRef, useState } from "react";
 import { useParams } from "react-router-dom";
-import { getMeetingById, sendSignalingMessage } from "../services/api"; 
+import { getMeetingById, openMeetingSocket } from "../services/api";
 import { Button, Spinner } from "react-bootstrap"; 
 
+// Frames sent before the socket opens, or after it closed, are dropped.
+const sendFrame = (ws, frame) => {
+  if (ws?.readyState === WebSocket.OPEN) {
+    ws.send(JSON.stringify(frame));
+  }
+};
+
 const MeetingRoom = () => {
   const { id } = useParams(); 
   const [meeting, setMeeting] = useState(null);
@@ -10,7 +17,10 @@ const MeetingRoom = () => {
   const [loading, setLoading] = useState(true);
   const localVideoRef = useRef(null);
   const remoteVideoRef = useRef(null);
-  const peerConnection = useRef(null); 
+  const peerConnection = useRef(null);
+  const socket = useRef(null);
+  // The session on the other end of the call, from `joined` or an offer.
+  const remotePeer = useRef(null);
   const [audioEnabled, setAudioEnabled] = useState(true);
   const [videoEnabled, setVideoEnabled] = useState(true);
   const [participants, setParticipants] = useState([]); 
@@ -31,60 +41,189 @@ const MeetingRoom = () => {
     fetchMeetingDetails();
   }, [id]);
 
+  // Starts once the meeting has loaded, so the video elements exist, and
+  // hangs up when an error replaces them.
   useEffect(() => {
-    const setupWebRTC = async () => {
-      try {
-        const stream = await navigator.mediaDevices.getUserMedia({
-          video: true,
-          audio: true,
-        });
-        localVideoRef.current.srcObject = stream;
+    if (loading || error) {
+      return undefined;
+    }
+    let stream = null;
+    let closed = false;
 
-        peerConnection.current = new RTCPeerConnection();
+    const sendSignal = (target, signal) =>
+      sendFrame(socket.current, { type: "signal", target, signal });
 
-        stream
-          .getTracks()
-          .forEach((track) => peerConnection.current.addTrack(track, stream));
-
-        peerConnection.current.ontrack = (event) => {
-          const [remoteStream] = event.streams;
+    // One connection per remote session; a new peer gets a fresh one.
+    const createPeerConnection = (target) => {
+      peerConnection.current?.close();
+      remotePeer.current = target;
+      const connection = new RTCPeerConnection();
+      stream
+        .getTracks()
+        .forEach((track) => connection.addTrack(track, stream));
+      connection.ontrack = (event) => {
+        const [remoteStream] = event.streams;
+        if (remoteVideoRef.current) {
           remoteVideoRef.current.srcObject = remoteStream;
-        };
+        }
+      };
+      connection.onicecandidate = (event) => {
+        if (event.candidate) {
+          sendSignal(target, {
+            kind: "ice-candidate",
+            candidate: event.candidate.toJSON(),
+          });
+        }
+      };
+      peerConnection.current = connection;
+      return connection;
+    };
+
+    const call = async (target) => {
+      const connection =
+        remotePeer.current === target
+          ? peerConnection.current
+          : createPeerConnection(target);
+      const offer = await connection.createOffer();
+      await connection.setLocalDescription(offer);
+      sendSignal(target, {
+        kind: "offer",
+        sdp: connection.localDescription.toJSON(),
+      });
+    };
 
-        peerConnection.current.onicecandidate = (event) => {
-          if (event.candidate) {
-            sendSignalingMessage({ candidate: event.candidate }); 
+    const handleSignal = async (from, signal) => {
+      switch (signal.kind) {
+        case "offer": {
+          const connection =
+            remotePeer.current === from.session_id
+              ? peerConnection.current
+              : createPeerConnection(from.session_id);
+          await connection.setRemoteDescription(signal.sdp);
+          const answer = await connection.createAnswer();
+          await connection.setLocalDescription(answer);
+          sendSignal(from.session_id, {
+            kind: "answer",
+            sdp: connection.localDescription.toJSON(),
+          });
+          break;
+        }
+        case "answer":
+          if (remotePeer.current === from.session_id) {
+            await peerConnection.current.setRemoteDescription(signal.sdp);
           }
-        };
+          break;
+        case "ice-candidate":
+          if (remotePeer.current === from.session_id) {
+            await peerConnection.current.addIceCandidate(signal.candidate);
+          }
+          break;
+        case "renegotiate":
+          await call(from.session_id);
+          break;
+        default:
+          console.warn("Unknown signal:", signal.kind);
+      }
+    };
 
-        const offer = await peerConnection.current.createOffer();
-        await peerConnection.current.setLocalDescription(offer);
+    const hangUp = () => {
+      peerConnection.current?.close();
+      peerConnection.current = null;
+      remotePeer.current = null;
+      if (remoteVideoRef.current) {
+        remoteVideoRef.current.srcObject = null;
+      }
+    };
 
-        sendSignalingMessage({ offer });
+    const handleFrame = async (frame) => {
+      switch (frame.type) {
+        case "joined":
+          sendFrame(socket.current, {
+            type: "media",
+            muted: !stream.getAudioTracks()[0]?.enabled,
+            camera: !!stream.getVideoTracks()[0]?.enabled,
+          });
+          // Whoever joins last calls a session that is already there.
+          if (frame.peers.length > 0) {
+            await call(frame.peers[0].session_id);
+          }
+          break;
+        case "signal":
+          await handleSignal(frame.from, frame.signal);
+          break;
+        case "presence":
+          if (
+            frame.event === "left" &&
+            frame.peer.session_id === remotePeer.current
+          ) {
+            hangUp();
+          }
+          break;
+        case "removed":
+          hangUp();
+          setError("You were removed from this meeting.");
+          break;
+        case "error":
+          console.error("Meeting socket error:", frame.code, frame.message);
+          break;
+        default:
+          break;
+      }
+    };
 
-        // Handle incoming signaling messages (e.g., answer, ICE candidates)
-        // Add this logic inside a listener or WebSocket message handler
-        // Example (pseudo-code):
-        // signalingSocket.onmessage = async (message) => {
-        //   const { answer, candidate } = message;
-        //   if (answer) await peerConnection.current.setRemoteDescription(answer);
-        //   if (candidate) await peerConnection.current.addIceCandidate(candidate);
-        // };
+    const setupWebRTC = async () => {
+      try {
+        stream = await navigator.mediaDevices.getUserMedia({
+          video: true,
+          audio: true,
+        });
       } catch (err) {
         setError(
           "Failed to access media devices. Please ensure you have granted permissions."
         );
+        return;
+      }
+      if (closed || !localVideoRef.current) {
+        stream.getTracks().forEach((track) => track.stop());
+        return;
       }
+      localVideoRef.current.srcObject = stream;
+
+      socket.current = openMeetingSocket(id);
+      // Frames are handled one at a time, so an ICE candidate is never
+      // added before the description it belongs to.
+      let pending = Promise.resolve();
+      socket.current.onmessage = (message) => {
+        const frame = JSON.parse(message.data);
+        pending = pending
+          .then(() => handleFrame(frame))
+          .catch((err) => console.error("Failed to handle meeting frame:", err));
+      };
+      socket.current.onclose = (event) => {
+        if (!closed) {
+          hangUp();
+          console.warn("Meeting socket closed:", event.code, event.reason);
+        }
+      };
     };
 
     setupWebRTC();
-  }, []);
+
+    return () => {
+      closed = true;
+      socket.current?.close();
+      socket.current = null;
+      hangUp();
+      stream?.getTracks().forEach((track) => track.stop());
+    };
+  }, [id, loading, error]);
 
   const toggleAudio = () => {
     const stream = localVideoRef.current.srcObject;
     const audioTrack = stream.getAudioTracks()[0];
     audioTrack.enabled = !audioTrack.enabled;
     setAudioEnabled(!audioEnabled);
+    sendFrame(socket.current, { type: "media", muted: !audioTrack.enabled });
   };
 
   const toggleVideo = () => {
@@ -92,6 +231,7 @@ const MeetingRoom = () => {
     const videoTrack = stream.getVideoTracks()[0];
     videoTrack.enabled = !videoTrack.enabled;
     setVideoEnabled(!videoEnabled);
+    sendFrame(socket.current, { type: "media", camera: videoTrack.enabled });
   };
 
   const ParticipantsList = ({ participants }) => (
@@ -8,8 +8,8 @@ function Messages({ meetingId }) {
   useEffect(() => {
     const fetchMessages = async () => {
       try {
-        const response = await getMessagesForMeeting(meetingId);
-        setMessages(response);
+        const page = await getMessagesForMeeting(meetingId);
+        setMessages(page.messages);
       } catch (error) {
         console.error('Error fetching messages:', error);
       }
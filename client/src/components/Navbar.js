@@ -1,6 +1,6 @@
 import React, { useState, useEffect } from "react";
 import { Link, useNavigate } from "react-router-dom";
-import { getUserById } from "../services/api";
+import { getUserById, logoutUser } from "../services/api";
 import { Navbar, Nav, NavDropdown, Button } from "react-bootstrap";
 
 function NavbarComponent() {
@@ -34,9 +34,9 @@ function NavbarComponent() {
     }
   };
 
-  const handleLogout = () => {
+  const handleLogout = async () => {
     if (window.confirm("Are you sure you want to logout?")) {
-      localStorage.removeItem("token");
+      await logoutUser();
       setIsLoggedIn(false);
       navigate("/login");
     }
@@ -33,6 +33,64 @@ apiClient.interceptors.request.use(
   }
 );
 
+// Access tokens are short-lived. On a 401, trade the refresh token for a new
+// pair once and replay the request; concurrent requests share one refresh.
+let refreshInFlight = null;
+
+const refreshTokens = async () => {
+  const refreshToken = localStorage.getItem("refresh_token");
+  if (!refreshToken) {
+    throw new Error("No refresh token stored.");
+  }
+  const response = await axios.post(`${API_URL}token/refresh`, {
+    refresh_token: refreshToken,
+  });
+  storeTokens(response.data);
+  return response.data.token;
+};
+
+const storeTokens = ({ token, refresh_token }) => {
+  localStorage.setItem("token", token);
+  localStorage.setItem("refresh_token", refresh_token);
+};
+
+const clearTokens = () => {
+  localStorage.removeItem("token");
+  localStorage.removeItem("refresh_token");
+};
+
+apiClient.interceptors.response.use(
+  (response) => response,
+  async (error) => {
+    const original = error.config;
+    const isAuthRoute = ["/login", "/register", "/token/refresh"].includes(
+      original?.url
+    );
+    if (
+      error.response?.status !== 401 ||
+      !original ||
+      original._retried ||
+      isAuthRoute
+    ) {
+      return Promise.reject(error);
+    }
+
+    original._retried = true;
+    try {
+      refreshInFlight = refreshInFlight || refreshTokens();
+      const token = await refreshInFlight;
+      original.headers["Authorization"] = `Bearer ${token}`;
+      return apiClient(original);
+    } catch (refreshError) {
+      console.error("Session expired, please log in again.");
+      clearTokens();
+      return Promise.reject(error);
+    } finally {
+      refreshInFlight = null;
+    }
+  }
+);
+
 // Handle errors and log them
 const handleError = (error) => {
   if (error.response) {
@@ -67,8 +125,8 @@ export const loginUser = async (userData) => {
     const response = await apiClient.post("/login", userData);
     const { token } = response.data;
     if (token) {
-      localStorage.setItem("token", token);
-      console.info("Login successful, token stored.");
+      storeTokens(response.data);
+      console.info("Login successful, tokens stored.");
     } else {
       console.error("No token received from login response.");
     }
@@ -78,6 +136,16 @@ export const loginUser = async (userData) => {
   }
 };
 
+export const logoutUser = async ({ allDevices = false } = {}) => {
+  try {
+    await apiClient.post(allDevices ? "/logout/all" : "/logout");
+  } catch (error) {
+    console.error("Logout request failed:", handleError(error));
+  } finally {
+    clearTokens();
+  }
+};
+
 export const getUserById = async (userId) => {
   try {
     console.info("Fetching user with ID:", userId);
@@ -118,12 +186,14 @@ export const getMeetingById = async (id) => {
   }
 };
 
-export const sendSignalingMessage = async (message) => {
-  try {
-    await axios.post(`/api/signaling`, message);
-  } catch (err) {
-    console.error("Error sending signaling message:", err);
-  }
+// Opens the meeting WebSocket. Browsers cannot set an Authorization header
+// on it, so the access token goes in the query string.
+export const openMeetingSocket = (meetingId) => {
+  const url = new URL("ws/", API_URL);
+  url.protocol = url.protocol === "https:" ? "wss:" : "ws:";
+  url.searchParams.set("token", localStorage.getItem("token") || "");
+  url.searchParams.set("meeting_id", meetingId);
+  return new WebSocket(url, "convo.v1");
 };
 
 export const deleteMeeting = async (meetingId) => {
@@ -167,10 +237,14 @@ export const createMessage = async (messageData) => {
   }
 };
 
-export const getMessagesForMeeting = async (meetingId) => {
+// Resolves to `{ messages, next_cursor }`. Pass `{ before: next_cursor }`
+// to load the page of older messages.
+export const getMessagesForMeeting = async (meetingId, params = {}) => {
   try {
     console.info("Fetching messages for meeting with ID:", meetingId);
-    const response = await apiClient.get(`/api/messages/${meetingId}`);
+    const response = await apiClient.get(`/api/messages/${meetingId}`, {
+      params,
+    });
     return response.data;
   } catch (error) {
     throw handleError(error);
@@ -0,0 +1,437 @@
+//! Frames exchanged over the meeting WebSocket (`GET /ws/`), kept apart
+//! from the server so Rust clients can depend on the same schema.
+//!
+//! Every frame is a JSON text frame tagged by `type`. The protocol version
+//! is agreed on during the handshake through the `Sec-WebSocket-Protocol`
+//! header; see [`negotiate`].
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The newest protocol version. Spoken when a client does not ask for one.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Every version the server understands, oldest first.
+pub const SUPPORTED_VERSIONS: &[u16] = &[1];
+
+const SUBPROTOCOL_PREFIX: &str = "convo.v";
+
+/// The `Sec-WebSocket-Protocol` name of a version, e.g. `convo.v1`.
+pub fn subprotocol(version: u16) -> String {
+    format!("{}{}", SUBPROTOCOL_PREFIX, version)
+}
+
+/// The version named by a subprotocol such as `convo.v1`.
+pub fn parse_subprotocol(name: &str) -> Option<u16> {
+    name.trim().strip_prefix(SUBPROTOCOL_PREFIX)?.parse().ok()
+}
+
+/// Picks the newest supported version among the subprotocols a client
+/// offered, or `None` if it offered none of them.
+pub fn negotiate<'a>(offered: impl IntoIterator<Item = &'a str>) -> Option<u16> {
+    offered
+        .into_iter()
+        .filter_map(parse_subprotocol)
+        .filter(|version| SUPPORTED_VERSIONS.contains(version))
+        .max()
+}
+
+/// A session in a meeting room, identified by the user it authenticated as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Peer {
+    pub session_id: usize,
+    pub user_id: i32,
+}
+
+/// A stored chat message as pushed to a meeting. A deleted message has
+/// empty content and `deleted_at` set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub id: i32,
+    pub content: String,
+    pub sender_id: i32,
+    pub meeting_id: i32,
+    pub timestamp: NaiveDateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+/// WebRTC signaling payloads. The SDP and ICE candidate bodies are passed
+/// through untouched so the browser's own objects can be sent as-is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Signal {
+    Offer { sdp: Value },
+    Answer { sdp: Value },
+    IceCandidate { candidate: Value },
+    Renegotiate,
+}
+
+/// A frame sent by a client. A client that sets `id` gets exactly one reply
+/// carrying it: `ack` once the frame was handled, or `error`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientEnvelope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub frame: ClientFrame,
+}
+
+impl ClientEnvelope {
+    pub fn from_text(text: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(text)
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+impl From<ClientFrame> for ClientEnvelope {
+    fn from(frame: ClientFrame) -> Self {
+        ClientEnvelope { id: None, frame }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+    /// Enters a meeting room, leaving any room the session was in before.
+    Join {
+        meeting_id: i32,
+    },
+    Leave,
+    /// A message to store and push to the whole meeting.
+    Chat {
+        content: String,
+    },
+    /// Shows or hides the "is typing" indicator to the rest of the meeting.
+    /// Repeated `active: true` frames only keep the indicator alive; it
+    /// goes away on its own when they stop.
+    Typing {
+        active: bool,
+    },
+    /// Marks every message up to this one as read.
+    Read {
+        message_id: i32,
+    },
+    /// Asks for a page of the meeting's stored messages. `before` and
+    /// `after` take a `next_cursor` from an earlier page; without either
+    /// the page ends at the newest message.
+    History {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        before: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        after: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limit: Option<u32>,
+    },
+    /// Reports a change to the session's microphone or camera. Fields left
+    /// out keep their current value.
+    Media {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        muted: Option<bool>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        camera: Option<bool>,
+    },
+    /// Signaling addressed to one session in the same meeting.
+    Signal {
+        target: usize,
+        signal: Signal,
+    },
+    /// An application payload passed on to every other session in the
+    /// meeting.
+    Relay {
+        payload: Value,
+    },
+}
+
+/// A frame sent by the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerFrame {
+    /// The first frame of every connection.
+    Hello {
+        version: u16,
+        session_id: usize,
+    },
+    /// The session entered a meeting room. Lists the sessions already in it.
+    Joined {
+        session_id: usize,
+        meeting_id: i32,
+        peers: Vec<Peer>,
+    },
+    Signal {
+        from: Peer,
+        signal: Signal,
+    },
+    Relay {
+        from: Peer,
+        payload: Value,
+    },
+    Chat {
+        message: ChatMessage,
+    },
+    /// A message was edited; carries it with its new content.
+    ChatEdited {
+        message: ChatMessage,
+    },
+    /// A message was deleted; carries its tombstone.
+    ChatDeleted {
+        message: ChatMessage,
+    },
+    Typing {
+        meeting_id: i32,
+        peer: Peer,
+        active: bool,
+    },
+    /// The reply to a `history` frame, oldest message first. `next_cursor`
+    /// continues in the direction that was asked for and is absent on the
+    /// last page.
+    History {
+        meeting_id: i32,
+        messages: Vec<ChatMessage>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        next_cursor: Option<String>,
+    },
+    /// A participant's read marker moved forward.
+    Read {
+        meeting_id: i32,
+        user_id: i32,
+        message_id: i32,
+    },
+    /// Everyone in the meeting, sent right after `joined`.
+    Roster(Roster),
+    /// A change in who is connected to the meeting, or in their media.
+    Presence {
+        meeting_id: i32,
+        peer: Peer,
+        #[serde(flatten)]
+        event: PresenceEvent,
+    },
+    /// Sent to a session right before it is taken out of a meeting because
+    /// its user was removed from the participants or the meeting was
+    /// deleted.
+    Removed {
+        meeting_id: i32,
+    },
+    Ack {
+        id: String,
+    },
+    Error {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+impl ServerFrame {
+    pub fn error(id: Option<String>, code: ErrorCode, message: impl Into<String>) -> Self {
+        ServerFrame::Error {
+            id,
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(text)
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// The sessions in a meeting and their state. Also served by
+/// `GET /api/meetings/{id}/presence`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Roster {
+    pub meeting_id: i32,
+    pub entries: Vec<RosterEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RosterEntry {
+    pub peer: Peer,
+    pub status: PresenceStatus,
+    pub muted: bool,
+    pub camera: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceStatus {
+    Connected,
+    /// The connection dropped and the session may still come back.
+    Reconnecting,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PresenceEvent {
+    Joined {
+        /// The reconnecting session of the same user this one takes over.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        replaces: Option<usize>,
+    },
+    Left {
+        reason: LeaveReason,
+    },
+    /// The connection dropped without a close frame. Followed by `left`,
+    /// unless the user comes back in time and a new session's `joined`
+    /// names this one in `replaces`.
+    Reconnecting,
+    Muted,
+    Unmuted,
+    CameraOn,
+    CameraOff,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaveReason {
+    /// The session left the meeting or closed its connection.
+    Left,
+    /// No frame, not even a pong, arrived within the server's client
+    /// timeout.
+    Timeout,
+    /// The connection dropped and the user did not come back in time.
+    Disconnected,
+    /// The user was removed from the meeting's participants.
+    Removed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The text was not a frame of the negotiated protocol version.
+    MalformedFrame,
+    /// Binary frames are not part of the protocol.
+    UnsupportedFrame,
+    /// The frame needs a joined meeting, or its target is not in it.
+    NotInMeeting,
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    ValidationError,
+    ServiceUnavailable,
+    InternalError,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn negotiates_the_newest_supported_version() {
+        assert_eq!(subprotocol(1), "convo.v1");
+        assert_eq!(negotiate(["chat", " convo.v1", "convo.v99"]), Some(1));
+        assert_eq!(negotiate(["convo.v0", "convo.vx"]), None);
+        assert_eq!(negotiate([]), None);
+    }
+
+    #[test]
+    fn client_frames_carry_an_optional_id() {
+        let envelope =
+            ClientEnvelope::from_text(r#"{"id":"c1","type":"join","meeting_id":3}"#).unwrap();
+        assert_eq!(envelope.id.as_deref(), Some("c1"));
+        assert_eq!(envelope.frame, ClientFrame::Join { meeting_id: 3 });
+
+        let leave = ClientEnvelope::from_text(r#"{"type":"leave"}"#).unwrap();
+        assert_eq!(leave, ClientFrame::Leave.into());
+        assert_eq!(leave.to_text(), r#"{"type":"leave"}"#);
+
+        assert!(ClientEnvelope::from_text(r#"{"type":"shout"}"#).is_err());
+        assert!(ClientEnvelope::from_text(r#"{"type":"chat"}"#).is_err());
+    }
+
+    #[test]
+    fn presence_events_are_flattened_into_the_frame() {
+        let frame = ServerFrame::Presence {
+            meeting_id: 1,
+            peer: Peer {
+                session_id: 4,
+                user_id: 7,
+            },
+            event: PresenceEvent::Left {
+                reason: LeaveReason::Timeout,
+            },
+        };
+        let value: Value = serde_json::from_str(&frame.to_text()).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "type": "presence",
+                "meeting_id": 1,
+                "peer": { "session_id": 4, "user_id": 7 },
+                "event": "left",
+                "reason": "timeout",
+            })
+        );
+        assert_eq!(ServerFrame::from_text(&frame.to_text()).unwrap(), frame);
+
+        let joined = ServerFrame::Presence {
+            meeting_id: 1,
+            peer: Peer {
+                session_id: 4,
+                user_id: 7,
+            },
+            event: PresenceEvent::CameraOn,
+        };
+        assert!(joined.to_text().contains(r#""event":"camera_on""#));
+
+        let resumed = ServerFrame::from_text(
+            r#"{"type":"presence","meeting_id":1,"peer":{"session_id":5,"user_id":7},"event":"joined","replaces":4}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            resumed,
+            ServerFrame::Presence {
+                event: PresenceEvent::Joined { replaces: Some(4) },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn roster_frames_inline_the_roster() {
+        let roster = Roster {
+            meeting_id: 1,
+            entries: vec![RosterEntry {
+                peer: Peer {
+                    session_id: 4,
+                    user_id: 7,
+                },
+                status: PresenceStatus::Reconnecting,
+                muted: true,
+                camera: false,
+            }],
+        };
+        let value: Value =
+            serde_json::from_str(&ServerFrame::Roster(roster.clone()).to_text()).unwrap();
+        assert_eq!(value["type"], "roster");
+        assert_eq!(value["meeting_id"], 1);
+        assert_eq!(value["entries"][0]["status"], "reconnecting");
+        assert_eq!(
+            ServerFrame::from_text(&value.to_string()).unwrap(),
+            ServerFrame::Roster(roster)
+        );
+    }
+
+    #[test]
+    fn errors_name_a_code() {
+        let frame = ServerFrame::error(None, ErrorCode::MalformedFrame, "bad");
+        assert_eq!(
+            frame.to_text(),
+            r#"{"type":"error","code":"malformed_frame","message":"bad"}"#
+        );
+    }
+}
@@ -0,0 +1,12 @@
+CREATE TABLE meetings (
+    id SERIAL PRIMARY KEY,
+    title VARCHAR NOT NULL,
+    description VARCHAR,
+    start_time TIMESTAMP NOT NULL,
+    end_time TIMESTAMP NOT NULL,
+    creator_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    CHECK (start_time < end_time)
+);
+
+CREATE INDEX meetings_creator_id_idx ON meetings (creator_id);
+CREATE INDEX meetings_start_time_idx ON meetings (start_time);
@@ -0,0 +1,10 @@
+CREATE TABLE messages (
+    id SERIAL PRIMARY KEY,
+    content VARCHAR NOT NULL,
+    sender_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    meeting_id INTEGER NOT NULL REFERENCES meetings (id) ON DELETE CASCADE,
+    timestamp TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX messages_meeting_id_timestamp_idx ON messages (meeting_id, timestamp, id);
+CREATE INDEX messages_sender_id_idx ON messages (sender_id);
@@ -0,0 +1,13 @@
+CREATE TABLE meeting_participants (
+    meeting_id INTEGER NOT NULL REFERENCES meetings (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    role VARCHAR NOT NULL CHECK (role IN ('host', 'co_host', 'attendee', 'viewer')),
+    joined_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (meeting_id, user_id)
+);
+
+CREATE INDEX meeting_participants_user_id_idx ON meeting_participants (user_id);
+
+-- Every existing meeting is hosted by its creator.
+INSERT INTO meeting_participants (meeting_id, user_id, role)
+SELECT id, creator_id, 'host' FROM meetings;
@@ -0,0 +1,16 @@
+CREATE TABLE meeting_invitations (
+    id SERIAL PRIMARY KEY,
+    meeting_id INTEGER NOT NULL REFERENCES meetings (id) ON DELETE CASCADE,
+    inviter_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    invitee_user_id INTEGER REFERENCES users (id) ON DELETE CASCADE,
+    invitee_email VARCHAR,
+    role VARCHAR NOT NULL CHECK (role IN ('co_host', 'attendee', 'viewer')),
+    status VARCHAR NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'accepted', 'declined')),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK (invitee_user_id IS NOT NULL OR invitee_email IS NOT NULL)
+);
+
+CREATE INDEX meeting_invitations_meeting_id_idx ON meeting_invitations (meeting_id);
+CREATE INDEX meeting_invitations_invitee_user_id_idx ON meeting_invitations (invitee_user_id);
+CREATE INDEX meeting_invitations_invitee_email_idx ON meeting_invitations (lower(invitee_email));
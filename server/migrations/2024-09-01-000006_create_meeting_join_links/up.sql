@@ -0,0 +1,14 @@
+CREATE TABLE meeting_join_links (
+    id SERIAL PRIMARY KEY,
+    meeting_id INTEGER NOT NULL REFERENCES meetings (id) ON DELETE CASCADE,
+    created_by INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    role VARCHAR NOT NULL CHECK (role IN ('co_host', 'attendee', 'viewer')),
+    max_uses INTEGER CHECK (max_uses > 0),
+    uses INTEGER NOT NULL DEFAULT 0,
+    passcode_hash VARCHAR,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX meeting_join_links_meeting_id_idx ON meeting_join_links (meeting_id);
@@ -0,0 +1,12 @@
+-- The last message each participant has read in each meeting. Messages
+-- are ordered by (timestamp, id), so the message's timestamp is kept too.
+CREATE TABLE message_reads (
+    meeting_id INTEGER NOT NULL REFERENCES meetings (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    last_read_message_id INTEGER NOT NULL REFERENCES messages (id) ON DELETE CASCADE,
+    last_read_timestamp TIMESTAMP NOT NULL,
+    read_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (meeting_id, user_id)
+);
+
+CREATE INDEX message_reads_user_id_idx ON message_reads (user_id);
@@ -0,0 +1,4 @@
+DROP TABLE message_revisions;
+ALTER TABLE messages
+    DROP COLUMN deleted_at,
+    DROP COLUMN edited_at;
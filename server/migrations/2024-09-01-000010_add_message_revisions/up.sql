@@ -0,0 +1,16 @@
+-- A deleted message keeps its row as a tombstone with empty content.
+ALTER TABLE messages
+    ADD COLUMN edited_at TIMESTAMP,
+    ADD COLUMN deleted_at TIMESTAMP;
+
+-- The content a message had before each edit or delete.
+CREATE TABLE message_revisions (
+    id SERIAL PRIMARY KEY,
+    message_id INTEGER NOT NULL REFERENCES messages (id) ON DELETE CASCADE,
+    content VARCHAR NOT NULL,
+    action VARCHAR NOT NULL CHECK (action IN ('edit', 'delete')),
+    changed_by INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX message_revisions_message_id_idx ON message_revisions (message_id);
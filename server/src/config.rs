@@ -0,0 +1,729 @@
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use std::{fmt, fs};
+
+/// Used when neither `--config` nor `CONFIG_FILE` names a file. A missing
+/// default file is not an error; everything then comes from the environment
+/// and the command line.
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+pub const USAGE: &str = "\
+Usage: server [migrate] [OPTIONS]
+
+Commands:
+  migrate                  Apply pending migrations and exit
+
+Options:
+  --config <PATH>          TOML config file (default: config.toml if present)
+  --bind <ADDR>            Address to listen on
+  --port <PORT>            Port to listen on
+  --database-url <URL>     Postgres connection string
+  --cors-origins <LIST>    Comma-separated origins allowed by CORS
+  --public-routes <LIST>   Comma-separated paths served without a token
+  --shutdown-timeout <SECS>
+                           Seconds to drain connections when stopping
+  --log-format <FORMAT>    `text` or `json`
+  --log-level <FILTER>     Log filter, e.g. `info` or `server=debug`
+  --help                   Print this help
+
+Settings are read from the config file, then the environment, then these
+flags; later sources win.";
+
+/// Everything the server reads at startup. Loaded once by `Config::load`
+/// and shared with handlers as `web::Data<Config>`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub websocket: WebSocketConfig,
+    pub log: LogConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: IpAddr,
+    pub port: u16,
+    pub cors_origins: Vec<String>,
+    /// Paths served without a token. `None` keeps `PublicRoutes::default()`.
+    pub public_routes: Option<Vec<String>>,
+    /// How long a stopping server waits for in-flight requests and closing
+    /// WebSocket sessions before dropping them.
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 8080,
+            cors_origins: vec!["http://localhost:3000".to_string()],
+            public_routes: None,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    /// Apply pending migrations on startup.
+    pub auto_migrate: bool,
+    pub pool_max_size: u32,
+    pub pool_min_idle: Option<u32>,
+    pub connection_timeout_secs: u64,
+    pub idle_timeout_secs: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            auto_migrate: true,
+            pool_max_size: 10,
+            pool_min_idle: None,
+            connection_timeout_secs: 5,
+            idle_timeout_secs: 600,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub jwt_secret: String,
+    pub access_token_minutes: i64,
+    pub refresh_token_days: i64,
+}
+
+impl AuthConfig {
+    pub fn access_token_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.access_token_minutes)
+    }
+
+    pub fn refresh_token_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::days(self.refresh_token_days)
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            jwt_secret: String::new(),
+            access_token_minutes: 15,
+            refresh_token_days: 30,
+        }
+    }
+}
+
+/// Keeps the secret out of startup logs.
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("jwt_secret", &"<redacted>")
+            .field("access_token_minutes", &self.access_token_minutes)
+            .field("refresh_token_days", &self.refresh_token_days)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebSocketConfig {
+    /// How often the server pings each session.
+    pub heartbeat_interval_secs: u64,
+    /// A session that sent nothing, not even a pong, for this long is
+    /// dropped and its meeting told it timed out.
+    pub client_timeout_secs: u64,
+    /// How long a session whose connection dropped stays in its meeting's
+    /// roster as reconnecting. 0 reports it as gone right away.
+    pub reconnect_grace_secs: u64,
+    /// How long a typing indicator lasts without another `typing` frame.
+    pub typing_timeout_secs: u64,
+}
+
+impl WebSocketConfig {
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_secs(self.heartbeat_interval_secs)
+    }
+
+    pub fn client_timeout(&self) -> Duration {
+        Duration::from_secs(self.client_timeout_secs)
+    }
+
+    pub fn reconnect_grace(&self) -> Duration {
+        Duration::from_secs(self.reconnect_grace_secs)
+    }
+
+    pub fn typing_timeout(&self) -> Duration {
+        Duration::from_secs(self.typing_timeout_secs)
+    }
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        WebSocketConfig {
+            heartbeat_interval_secs: 10,
+            client_timeout_secs: 30,
+            reconnect_grace_secs: 30,
+            typing_timeout_secs: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// A `tracing` filter such as `info` or `server=debug,actix_web=warn`.
+    pub level: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            format: LogFormat::Text,
+            level: "info".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("expected `text` or `json`, got `{}`", other)),
+        }
+    }
+}
+
+/// A setting that can be overridden from the environment or the command
+/// line, named by its dotted key in the config file.
+#[derive(Debug)]
+struct Setting {
+    key: &'static str,
+    env: &'static str,
+    flag: Option<&'static str>,
+}
+
+const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "server.bind_address",
+        env: "SERVER_BIND_ADDRESS",
+        flag: Some("--bind"),
+    },
+    Setting {
+        key: "server.port",
+        env: "SERVER_PORT",
+        flag: Some("--port"),
+    },
+    Setting {
+        key: "server.cors_origins",
+        env: "CORS_ORIGINS",
+        flag: Some("--cors-origins"),
+    },
+    Setting {
+        key: "server.public_routes",
+        env: "PUBLIC_ROUTES",
+        flag: Some("--public-routes"),
+    },
+    Setting {
+        key: "server.shutdown_timeout_secs",
+        env: "SHUTDOWN_TIMEOUT_SECS",
+        flag: Some("--shutdown-timeout"),
+    },
+    Setting {
+        key: "database.url",
+        env: "DATABASE_URL",
+        flag: Some("--database-url"),
+    },
+    Setting {
+        key: "database.auto_migrate",
+        env: "AUTO_MIGRATE",
+        flag: None,
+    },
+    Setting {
+        key: "database.pool_max_size",
+        env: "DB_POOL_MAX_SIZE",
+        flag: None,
+    },
+    Setting {
+        key: "database.pool_min_idle",
+        env: "DB_POOL_MIN_IDLE",
+        flag: None,
+    },
+    Setting {
+        key: "database.connection_timeout_secs",
+        env: "DB_POOL_CONNECTION_TIMEOUT_SECS",
+        flag: None,
+    },
+    Setting {
+        key: "database.idle_timeout_secs",
+        env: "DB_POOL_IDLE_TIMEOUT_SECS",
+        flag: None,
+    },
+    Setting {
+        key: "auth.jwt_secret",
+        env: "JWT_SECRET",
+        flag: None,
+    },
+    Setting {
+        key: "auth.access_token_minutes",
+        env: "ACCESS_TOKEN_MINUTES",
+        flag: None,
+    },
+    Setting {
+        key: "auth.refresh_token_days",
+        env: "REFRESH_TOKEN_DAYS",
+        flag: None,
+    },
+    Setting {
+        key: "websocket.heartbeat_interval_secs",
+        env: "WS_HEARTBEAT_INTERVAL_SECS",
+        flag: None,
+    },
+    Setting {
+        key: "websocket.client_timeout_secs",
+        env: "WS_CLIENT_TIMEOUT_SECS",
+        flag: None,
+    },
+    Setting {
+        key: "websocket.reconnect_grace_secs",
+        env: "WS_RECONNECT_GRACE_SECS",
+        flag: None,
+    },
+    Setting {
+        key: "websocket.typing_timeout_secs",
+        env: "WS_TYPING_TIMEOUT_SECS",
+        flag: None,
+    },
+    Setting {
+        key: "log.format",
+        env: "LOG_FORMAT",
+        flag: Some("--log-format"),
+    },
+    Setting {
+        key: "log.level",
+        env: "RUST_LOG",
+        flag: Some("--log-level"),
+    },
+];
+
+/// Why the configuration could not be loaded. Lists every problem found,
+/// not just the first, so they can all be fixed in one go.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl ConfigError {
+    fn single(problem: String) -> Self {
+        ConfigError {
+            problems: vec![problem],
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    Migrate,
+    Help,
+}
+
+/// The parsed command line: what to do, and the flags that override
+/// settings.
+#[derive(Debug)]
+pub struct Cli {
+    pub command: Command,
+    config_file: Option<PathBuf>,
+    overrides: Vec<(&'static Setting, String)>,
+}
+
+impl Cli {
+    /// Parses the arguments after the program name. Flags take their value
+    /// either as the next argument or after `=`.
+    pub fn parse<I>(args: I) -> Result<Cli, ConfigError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut cli = Cli {
+            command: Command::Serve,
+            config_file: None,
+            overrides: Vec::new(),
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                cli.command = Command::Help;
+                continue;
+            }
+            if !arg.starts_with("--") {
+                match arg.as_str() {
+                    "migrate" if cli.command == Command::Serve => cli.command = Command::Migrate,
+                    _ => {
+                        return Err(ConfigError::single(format!(
+                            "unexpected argument `{}`",
+                            arg
+                        )))
+                    }
+                }
+                continue;
+            }
+
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let setting = match SETTINGS.iter().find(|s| s.flag == Some(flag.as_str())) {
+                Some(setting) => Some(setting),
+                None if flag == "--config" => None,
+                None => {
+                    return Err(ConfigError::single(format!(
+                        "unknown option `{}`; see --help",
+                        flag
+                    )))
+                }
+            };
+            let value = match inline_value.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(ConfigError::single(format!("{} needs a value", flag))),
+            };
+            match setting {
+                Some(setting) => cli.overrides.push((setting, value)),
+                None => cli.config_file = Some(PathBuf::from(value)),
+            }
+        }
+        Ok(cli)
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    value.trim().parse().map_err(|e: T::Err| e.to_string())
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl Config {
+    /// Builds the configuration from, in increasing precedence, the
+    /// defaults, the TOML file, the environment (read through `env`) and
+    /// the command-line flags, then validates the result.
+    pub fn load(cli: &Cli, env: impl Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+        let mut config = match cli
+            .config_file
+            .clone()
+            .or_else(|| env("CONFIG_FILE").map(PathBuf::from))
+        {
+            Some(path) => Config::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Config::default(),
+        };
+
+        let mut problems = Vec::new();
+        for setting in SETTINGS {
+            if let Some(value) = env(setting.env) {
+                if let Err(e) = config.set(setting.key, &value) {
+                    problems.push(format!("{}: {}", setting.env, e));
+                }
+            }
+        }
+        for (setting, value) in &cli.overrides {
+            if let Err(e) = config.set(setting.key, value) {
+                problems.push(format!("{}: {}", setting.flag.unwrap_or(setting.key), e));
+            }
+        }
+
+        problems.extend(config.problems());
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| ConfigError::single(format!("cannot read {}: {}", path.display(), e)))?;
+        Config::from_toml(&contents)
+            .map_err(|e| ConfigError::single(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Config, String> {
+        toml::from_str(contents).map_err(|e| e.message().to_string())
+    }
+
+    /// Overrides one setting from its string form.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "server.bind_address" => self.server.bind_address = parse(value)?,
+            "server.port" => self.server.port = parse(value)?,
+            "server.cors_origins" => self.server.cors_origins = parse_list(value),
+            "server.public_routes" => self.server.public_routes = Some(parse_list(value)),
+            "server.shutdown_timeout_secs" => self.server.shutdown_timeout_secs = parse(value)?,
+            "database.url" => self.database.url = value.to_string(),
+            "database.auto_migrate" => self.database.auto_migrate = parse(value)?,
+            "database.pool_max_size" => self.database.pool_max_size = parse(value)?,
+            "database.pool_min_idle" => self.database.pool_min_idle = Some(parse(value)?),
+            "database.connection_timeout_secs" => {
+                self.database.connection_timeout_secs = parse(value)?
+            }
+            "database.idle_timeout_secs" => self.database.idle_timeout_secs = parse(value)?,
+            "auth.jwt_secret" => self.auth.jwt_secret = value.to_string(),
+            "auth.access_token_minutes" => self.auth.access_token_minutes = parse(value)?,
+            "auth.refresh_token_days" => self.auth.refresh_token_days = parse(value)?,
+            "websocket.heartbeat_interval_secs" => {
+                self.websocket.heartbeat_interval_secs = parse(value)?
+            }
+            "websocket.client_timeout_secs" => self.websocket.client_timeout_secs = parse(value)?,
+            "websocket.reconnect_grace_secs" => self.websocket.reconnect_grace_secs = parse(value)?,
+            "websocket.typing_timeout_secs" => self.websocket.typing_timeout_secs = parse(value)?,
+            "log.format" => self.log.format = parse(value)?,
+            "log.level" => self.log.level = value.to_string(),
+            _ => unreachable!("unknown setting {}", key),
+        }
+        Ok(())
+    }
+
+    /// Checks the values that parse fine but cannot work.
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.database.url.trim().is_empty() {
+            problems.push("database.url must be set (DATABASE_URL or --database-url)".to_string());
+        }
+        if self.auth.jwt_secret.is_empty() {
+            problems.push("auth.jwt_secret must be set (JWT_SECRET)".to_string());
+        }
+        if self.database.pool_max_size == 0 {
+            problems.push("database.pool_max_size must be at least 1".to_string());
+        }
+        if let Some(min_idle) = self.database.pool_min_idle {
+            if min_idle > self.database.pool_max_size {
+                problems.push(format!(
+                    "database.pool_min_idle ({}) must not exceed database.pool_max_size ({})",
+                    min_idle, self.database.pool_max_size
+                ));
+            }
+        }
+        if self.database.connection_timeout_secs == 0 {
+            problems.push("database.connection_timeout_secs must be at least 1".to_string());
+        }
+        if self.auth.access_token_minutes < 1 {
+            problems.push("auth.access_token_minutes must be at least 1".to_string());
+        }
+        if self.auth.refresh_token_days < 1 {
+            problems.push("auth.refresh_token_days must be at least 1".to_string());
+        } else if self.auth.refresh_token_lifetime() <= self.auth.access_token_lifetime() {
+            problems
+                .push("auth.refresh_token_days must outlast auth.access_token_minutes".to_string());
+        }
+        if self.websocket.heartbeat_interval_secs == 0 {
+            problems.push("websocket.heartbeat_interval_secs must be at least 1".to_string());
+        } else if self.websocket.client_timeout_secs <= self.websocket.heartbeat_interval_secs {
+            problems.push(
+                "websocket.client_timeout_secs must exceed websocket.heartbeat_interval_secs"
+                    .to_string(),
+            );
+        }
+        if self.websocket.typing_timeout_secs == 0 {
+            problems.push("websocket.typing_timeout_secs must be at least 1".to_string());
+        }
+        for origin in &self.server.cors_origins {
+            let valid = origin
+                .strip_prefix("http://")
+                .or_else(|| origin.strip_prefix("https://"))
+                .is_some_and(|host| !host.is_empty() && !host.contains('/'));
+            if !valid {
+                problems.push(format!(
+                    "server.cors_origins: `{}` is not an origin like `https://app.example.com`",
+                    origin
+                ));
+            }
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log.level) {
+            problems.push(format!("log.level: {}", e));
+        }
+        if let Some(routes) = &self.server.public_routes {
+            for route in routes.iter().filter(|route| !route.starts_with('/')) {
+                problems.push(format!(
+                    "server.public_routes: `{}` must start with `/`",
+                    route
+                ));
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::parse(args.iter().map(|arg| arg.to_string())).expect("valid arguments")
+    }
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    const REQUIRED: [(&str, &str); 2] = [
+        ("DATABASE_URL", "postgres://localhost/convo"),
+        ("JWT_SECRET", "secret"),
+    ];
+
+    #[test]
+    fn later_sources_override_earlier_ones() {
+        let dir = std::env::temp_dir().join(format!("convo-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("config.toml");
+        fs::write(
+            &file,
+            "[server]\nport = 9000\ncors_origins = [\"https://file.example\"]\n\
+             [log]\nformat = \"json\"\n",
+        )
+        .unwrap();
+
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("SERVER_PORT", "9100"));
+        vars.push(("CORS_ORIGINS", "https://env.example, https://other.example"));
+        let config = Config::load(
+            &cli(&["--config", file.to_str().unwrap(), "--port=9200"]),
+            env(&vars),
+        )
+        .unwrap();
+
+        assert_eq!(config.server.port, 9200);
+        assert_eq!(
+            config.server.cors_origins,
+            ["https://env.example", "https://other.example"]
+        );
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert_eq!(config.database.pool_max_size, 10);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let err = Config::load(
+            &cli(&["--cors-origins", "localhost:3000"]),
+            env(&[("DB_POOL_MAX_SIZE", "lots"), ("ACCESS_TOKEN_MINUTES", "0")]),
+        )
+        .unwrap_err();
+
+        let message = err.to_string();
+        assert_eq!(err.problems.len(), 5, "{}", message);
+        assert!(message.contains("DB_POOL_MAX_SIZE: invalid digit"));
+        assert!(message.contains("database.url must be set"));
+        assert!(message.contains("auth.jwt_secret must be set"));
+        assert!(message.contains("auth.access_token_minutes must be at least 1"));
+        assert!(message.contains("`localhost:3000` is not an origin"));
+    }
+
+    #[test]
+    fn example_file_matches_the_defaults() {
+        let example = Config::from_toml(include_str!("../config.example.toml")).unwrap();
+        let defaults = Config::default();
+        assert_eq!(example.server.port, defaults.server.port);
+        assert_eq!(example.server.cors_origins, defaults.server.cors_origins);
+        assert_eq!(
+            example.server.shutdown_timeout_secs,
+            defaults.server.shutdown_timeout_secs
+        );
+        assert_eq!(
+            example.database.pool_max_size,
+            defaults.database.pool_max_size
+        );
+        assert_eq!(
+            example.auth.access_token_minutes,
+            defaults.auth.access_token_minutes
+        );
+        assert_eq!(
+            example.websocket.client_timeout_secs,
+            defaults.websocket.client_timeout_secs
+        );
+        assert_eq!(
+            example.websocket.reconnect_grace_secs,
+            defaults.websocket.reconnect_grace_secs
+        );
+        assert_eq!(
+            example.websocket.typing_timeout_secs,
+            defaults.websocket.typing_timeout_secs
+        );
+        assert_eq!(example.log.format, defaults.log.format);
+    }
+
+    #[test]
+    fn heartbeat_must_be_shorter_than_the_timeout() {
+        let mut vars = REQUIRED.to_vec();
+        vars.push(("WS_HEARTBEAT_INTERVAL_SECS", "30"));
+        let err = Config::load(&cli(&[]), env(&vars)).unwrap_err();
+        assert_eq!(
+            err.problems,
+            ["websocket.client_timeout_secs must exceed websocket.heartbeat_interval_secs"]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_flags() {
+        assert!(Config::from_toml("[server]\nprot = 1\n")
+            .unwrap_err()
+            .contains("unknown field `prot`"));
+        assert!(Cli::parse(["--prot".to_string(), "1".to_string()]).is_err());
+        assert!(Cli::parse(["--port".to_string()]).is_err());
+    }
+
+    #[test]
+    fn parses_the_migrate_command() {
+        assert_eq!(cli(&[]).command, Command::Serve);
+        assert_eq!(
+            cli(&["migrate", "--log-level", "debug"]).command,
+            Command::Migrate
+        );
+        assert_eq!(cli(&["--help"]).command, Command::Help);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::config::DatabaseConfig;
+use actix_web::web;
+use diesel::pg::PgConnection;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use std::time::Duration;
+
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+
+/// The versioned schema in `migrations/`, compiled into the binary.
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// Builds the shared connection pool.
+///
+/// The pool is built without opening any connections, so the server still
+/// starts while the database is down and requests fail with 503 until it
+/// comes back.
+pub fn init_pool(config: &DatabaseConfig) -> DbPool {
+    let manager = ConnectionManager::<PgConnection>::new(config.url.clone());
+
+    tracing::info!(
+        max_size = config.pool_max_size,
+        connection_timeout_secs = config.connection_timeout_secs,
+        "configuring database pool"
+    );
+
+    Pool::builder()
+        .max_size(config.pool_max_size)
+        .min_idle(config.pool_min_idle)
+        .connection_timeout(Duration::from_secs(config.connection_timeout_secs))
+        .idle_timeout(Some(Duration::from_secs(config.idle_timeout_secs)))
+        .build_unchecked(manager)
+}
+
+/// Applies every migration that has not run yet and returns the versions
+/// that were applied.
+pub fn run_migrations(conn: &mut PgConnection) -> Result<Vec<String>, String> {
+    let applied = conn
+        .run_pending_migrations(MIGRATIONS)
+        .map_err(|e| format!("Failed to run migrations: {}", e))?;
+    Ok(applied.iter().map(|version| version.to_string()).collect())
+}
+
+#[derive(Debug)]
+pub enum DbError {
+    /// No connection could be checked out of the pool in time.
+    Unavailable(r2d2::Error),
+    /// The blocking task was cancelled before it finished.
+    Canceled(actix_web::error::BlockingError),
+    Query(diesel::result::Error),
+}
+
+impl std::fmt::Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DbError::Unavailable(e) => write!(f, "database unavailable: {}", e),
+            DbError::Canceled(e) => write!(f, "database task canceled: {}", e),
+            DbError::Query(e) => write!(f, "query failed: {}", e),
+        }
+    }
+}
+
+/// Runs blocking diesel work on actix's blocking thread pool so the calling
+/// worker keeps serving other requests while the query is in flight.
+pub async fn run<F, T>(pool: &DbPool, f: F) -> Result<T, DbError>
+where
+    F: FnOnce(&mut PgConnection) -> Result<T, diesel::result::Error> + Send + 'static,
+    T: Send + 'static,
+{
+    let pool = pool.clone();
+    web::block(move || {
+        let mut conn = pool.get().map_err(DbError::Unavailable)?;
+        f(&mut conn).map_err(DbError::Query)
+    })
+    .await
+    .map_err(DbError::Canceled)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App, HttpResponse};
+    use diesel::RunQueryDsl;
+    use futures::future::join_all;
+    use std::env;
+    use std::time::Instant;
+
+    const CONCURRENT_REQUESTS: usize = 16;
+
+    fn test_pool() -> DbPool {
+        let database_url = env::var("TEST_DATABASE_URL")
+            .expect("TEST_DATABASE_URL must name a throwaway database to run database tests");
+        Pool::builder()
+            .max_size(CONCURRENT_REQUESTS as u32)
+            .build(ConnectionManager::<PgConnection>::new(database_url))
+            .expect("TEST_DATABASE_URL should point at a reachable database")
+    }
+
+    fn slow_query(conn: &mut PgConnection) -> Result<usize, diesel::result::Error> {
+        diesel::sql_query("SELECT pg_sleep(0.05)").execute(conn)
+    }
+
+    async fn inline_handler(pool: web::Data<DbPool>) -> HttpResponse {
+        let mut conn = pool.get().expect("pool connection");
+        slow_query(&mut conn).expect("slow query");
+        HttpResponse::Ok().finish()
+    }
+
+    async fn offloaded_handler(pool: web::Data<DbPool>) -> HttpResponse {
+        run(&pool, slow_query).await.expect("slow query");
+        HttpResponse::Ok().finish()
+    }
+
+    async fn time_concurrent_requests(pool: &DbPool, path: &str) -> Duration {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .route("/inline", web::get().to(inline_handler))
+                .route("/offloaded", web::get().to(offloaded_handler)),
+        )
+        .await;
+
+        let started = Instant::now();
+        let responses =
+            join_all((0..CONCURRENT_REQUESTS).map(|_| {
+                test::call_service(&app, test::TestRequest::get().uri(path).to_request())
+            }))
+            .await;
+        let elapsed = started.elapsed();
+
+        assert!(responses.iter().all(|res| res.status().is_success()));
+        elapsed
+    }
+
+    /// Load test: a worker that awaits `run` keeps accepting requests while
+    /// queries are in flight, whereas calling diesel inline serializes them.
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn offloaded_queries_serve_concurrent_requests_faster() {
+        let pool = test_pool();
+
+        let inline = time_concurrent_requests(&pool, "/inline").await;
+        let offloaded = time_concurrent_requests(&pool, "/offloaded").await;
+        eprintln!(
+            "{} concurrent requests: inline {:?}, offloaded {:?}",
+            CONCURRENT_REQUESTS, inline, offloaded
+        );
+
+        assert!(
+            offloaded * 4 < inline,
+            "expected offloaded queries to be at least 4x faster (inline {:?}, offloaded {:?})",
+            inline,
+            offloaded
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use crate::db::DbError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde::Serialize;
+use std::fmt;
+
+/// Error type shared by all handlers. Every variant renders as
+/// `{"code": "...", "message": "..."}` with a matching status code.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    /// A 403 whose body also names the role or permission that was missing,
+    /// e.g. `"required": "admin"`.
+    MissingPermission {
+        required: &'static str,
+        message: String,
+    },
+    NotFound(String),
+    Conflict(String),
+    Validation(String),
+    ServiceUnavailable(String),
+    /// The detail is logged but never sent to the client.
+    Internal(String),
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    required: Option<&'a str>,
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) | ApiError::MissingPermission { .. } => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Validation(_) => "validation_error",
+            ApiError::ServiceUnavailable(_) => "service_unavailable",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::MissingPermission { message, .. }
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::Validation(message)
+            | ApiError::ServiceUnavailable(message) => message,
+            ApiError::Internal(_) => "Internal server error",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Internal(detail) => write!(f, "{}: {}", self.code(), detail),
+            _ => write!(f, "{}: {}", self.code(), self.message()),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) | ApiError::MissingPermission { .. } => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.status_code().is_server_error() {
+            tracing::error!(error = %self, "request failed");
+        }
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            code: self.code(),
+            message: self.message(),
+            required: match self {
+                ApiError::MissingPermission { required, .. } => Some(required),
+                _ => None,
+            },
+        })
+    }
+}
+
+impl From<DieselError> for ApiError {
+    fn from(e: DieselError) -> Self {
+        match e {
+            DieselError::NotFound => ApiError::NotFound("Resource not found".to_string()),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                ApiError::Conflict("Resource already exists".to_string())
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::CheckViolation, info) => {
+                ApiError::Validation(format!("Invalid value: {}", info.message()))
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) => {
+                ApiError::Validation(format!(
+                    "Referenced resource does not exist: {}",
+                    info.message()
+                ))
+            }
+            e => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<DbError> for ApiError {
+    fn from(e: DbError) -> Self {
+        match e {
+            DbError::Unavailable(e) => {
+                tracing::error!(error = %e, "failed to get a database connection");
+                ApiError::ServiceUnavailable("Database unavailable".to_string())
+            }
+            DbError::Canceled(e) => ApiError::Internal(e.to_string()),
+            DbError::Query(e) => e.into(),
+        }
+    }
+}
+
+impl From<bcrypt::BcryptError> for ApiError {
+    fn from(e: bcrypt::BcryptError) -> Self {
+        ApiError::Internal(format!("password hashing failed: {}", e))
+    }
+}
+
+/// Renders malformed JSON bodies and path segments with the same shape as
+/// every other error instead of actix's plain-text default.
+pub fn json_error_handler(
+    err: actix_web::error::JsonPayloadError,
+    _: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    ApiError::BadRequest(err.to_string()).into()
+}
+
+pub fn path_error_handler(
+    err: actix_web::error::PathError,
+    _: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    ApiError::BadRequest(err.to_string()).into()
+}
+
+pub fn query_error_handler(
+    err: actix_web::error::QueryPayloadError,
+    _: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    ApiError::BadRequest(err.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use actix_web::{web, App};
+    use serde_json::{json, Value};
+
+    async fn render(e: ApiError) -> (StatusCode, Value) {
+        let res = e.error_response();
+        let status = res.status();
+        let body = to_bytes(res.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn every_variant_renders_its_status_and_json_body() {
+        let cases = [
+            (ApiError::BadRequest("bad".into()), 400, "bad_request"),
+            (ApiError::Unauthorized("who?".into()), 401, "unauthorized"),
+            (ApiError::Forbidden("no".into()), 403, "forbidden"),
+            (ApiError::NotFound("gone".into()), 404, "not_found"),
+            (ApiError::Conflict("taken".into()), 409, "conflict"),
+            (
+                ApiError::Validation("short".into()),
+                422,
+                "validation_error",
+            ),
+            (
+                ApiError::ServiceUnavailable("down".into()),
+                503,
+                "service_unavailable",
+            ),
+        ];
+        for (e, status, code) in cases {
+            let message = e.message().to_string();
+            let (actual_status, body) = render(e).await;
+            assert_eq!(actual_status.as_u16(), status, "{}", code);
+            assert_eq!(body, json!({ "code": code, "message": message }));
+        }
+    }
+
+    #[actix_web::test]
+    async fn missing_permissions_name_what_was_required() {
+        let (status, body) = render(ApiError::MissingPermission {
+            required: "admin",
+            message: "This action requires the admin role".into(),
+        })
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            body,
+            json!({
+                "code": "forbidden",
+                "message": "This action requires the admin role",
+                "required": "admin",
+            })
+        );
+    }
+
+    #[actix_web::test]
+    async fn internal_errors_hide_their_detail() {
+        let (status, body) = render(ApiError::Internal("connection reset by peer".into())).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            body,
+            json!({ "code": "internal_error", "message": "Internal server error" })
+        );
+    }
+
+    #[actix_web::test]
+    async fn malformed_json_bodies_render_as_bad_request() {
+        async fn echo(body: web::Json<Value>) -> HttpResponse {
+            HttpResponse::Ok().json(body.into_inner())
+        }
+        let app = init_service(
+            App::new()
+                .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+                .route("/", web::post().to(echo)),
+        )
+        .await;
+        let req = TestRequest::post()
+            .uri("/")
+            .insert_header(("content-type", "application/json"))
+            .set_payload("{not json")
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: Value = read_body_json(res).await;
+        assert_eq!(body["code"], "bad_request");
+    }
+
+    #[test]
+    fn diesel_errors_map_to_not_found_and_conflict() {
+        assert!(matches!(
+            ApiError::from(DieselError::NotFound),
+            ApiError::NotFound(_)
+        ));
+        let unique = DieselError::DatabaseError(
+            DatabaseErrorKind::UniqueViolation,
+            Box::new("duplicate key".to_string()),
+        );
+        assert!(matches!(ApiError::from(unique), ApiError::Conflict(_)));
+    }
+}
@@ -0,0 +1,172 @@
+use crate::db::{self, DbError, DbPool};
+use crate::errors::ApiError;
+use crate::middleware::request_claims;
+use crate::models::{Claims, ParticipantRole, UserRole};
+use crate::repository::Repository;
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use diesel::result::Error as DieselError;
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::marker::PhantomData;
+
+/// A server-wide role that `RequireRole` can demand.
+pub trait RoleRequirement {
+    const MINIMUM: UserRole;
+}
+
+pub struct Admin;
+
+impl RoleRequirement for Admin {
+    const MINIMUM: UserRole = UserRole::Admin;
+}
+
+/// Any registered account except guests.
+pub struct Member;
+
+impl RoleRequirement for Member {
+    const MINIMUM: UserRole = UserRole::Member;
+}
+
+/// Extractor that only lets the request through when the caller's role is
+/// at least `R::MINIMUM`, e.g. `RequireRole<Admin>`.
+pub struct RequireRole<R> {
+    pub claims: Claims,
+    _role: PhantomData<R>,
+}
+
+impl<R: RoleRequirement> FromRequest for RequireRole<R> {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(request_claims(req).and_then(|claims| {
+            if claims.role < R::MINIMUM {
+                tracing::info!(
+                    user_id = %claims.sub,
+                    role = claims.role.as_str(),
+                    required = R::MINIMUM.as_str(),
+                    "missing role"
+                );
+                return Err(ApiError::MissingPermission {
+                    required: R::MINIMUM.as_str(),
+                    message: format!("This action requires the {} role", R::MINIMUM.as_str()),
+                });
+            }
+            Ok(RequireRole {
+                claims,
+                _role: PhantomData,
+            })
+        }))
+    }
+}
+
+/// Something a participant may or may not do in a meeting.
+pub trait MeetingPermission {
+    const NAME: &'static str;
+
+    fn allows(role: ParticipantRole) -> bool;
+}
+
+/// Any participant, viewers included.
+pub struct View;
+
+impl MeetingPermission for View {
+    const NAME: &'static str = "meeting.view";
+
+    fn allows(_: ParticipantRole) -> bool {
+        true
+    }
+}
+
+/// Participants who may post messages, i.e. everyone but viewers.
+pub struct Post;
+
+impl MeetingPermission for Post {
+    const NAME: &'static str = "meeting.post";
+
+    fn allows(role: ParticipantRole) -> bool {
+        role.can_post()
+    }
+}
+
+pub struct Manage;
+
+impl MeetingPermission for Manage {
+    const NAME: &'static str = "meeting.manage";
+
+    fn allows(role: ParticipantRole) -> bool {
+        role.can_manage()
+    }
+}
+
+/// Extractor for routes with a `{meeting_id}` or `{id}` segment naming a
+/// meeting. Succeeds when the caller is a participant whose role grants
+/// `P`, e.g. `MeetingAccess<Manage>`.
+pub struct MeetingAccess<P> {
+    pub claims: Claims,
+    pub meeting_id: i32,
+    pub role: ParticipantRole,
+    _permission: PhantomData<P>,
+}
+
+fn meeting_id_from_path(req: &HttpRequest) -> Result<i32, ApiError> {
+    let segment = req
+        .match_info()
+        .get("meeting_id")
+        .or_else(|| req.match_info().get("id"))
+        .ok_or_else(|| ApiError::Internal(format!("no meeting id in route {}", req.path())))?;
+    segment
+        .parse()
+        .map_err(|_| ApiError::BadRequest(format!("Invalid meeting id: {}", segment)))
+}
+
+impl<P: MeetingPermission + 'static> FromRequest for MeetingAccess<P> {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let claims = request_claims(req);
+        let meeting_id = meeting_id_from_path(req);
+        let pool = req.app_data::<web::Data<DbPool>>().cloned();
+
+        Box::pin(async move {
+            let claims = claims?;
+            let meeting_id = meeting_id?;
+            let pool =
+                pool.ok_or_else(|| ApiError::Internal("database pool not configured".to_string()))?;
+            let user_id = claims.user_id()?;
+
+            let participant = db::run(&pool, move |conn| conn.get_participant(meeting_id, user_id))
+                .await
+                .map_err(|e| match e {
+                    DbError::Query(DieselError::NotFound) => ApiError::MissingPermission {
+                        required: P::NAME,
+                        message: "You are not a participant in this meeting".to_string(),
+                    },
+                    e => e.into(),
+                })?;
+            if !P::allows(participant.role) {
+                tracing::info!(
+                    user_id,
+                    meeting_id,
+                    role = participant.role.as_str(),
+                    required = P::NAME,
+                    "missing meeting permission"
+                );
+                return Err(ApiError::MissingPermission {
+                    required: P::NAME,
+                    message: format!(
+                        "Your role ({}) does not allow this in the meeting",
+                        participant.role.as_str()
+                    ),
+                });
+            }
+
+            Ok(MeetingAccess {
+                claims,
+                meeting_id,
+                role: participant.role,
+                _permission: PhantomData,
+            })
+        })
+    }
+}
@@ -1,183 +1,1154 @@
-use crate::models::{Claims, LoginRequest, NewMeeting, NewMessage, NewUser};
+use crate::config::{AuthConfig, Config};
+use crate::db::{self, DbError, DbPool};
+use crate::errors::ApiError;
+use crate::guards::{Admin, Manage, MeetingAccess, Member, Post, RequireRole, View};
+use crate::metrics::Metrics;
+use crate::models::{
+    AddParticipantRequest, Claims, CreateInvitationRequest, CreateJoinLinkRequest,
+    CreateMeetingRequest, CreateMessageRequest, EditMessageRequest, InvitationStatus, JoinLink,
+    JoinLinkResponse, LoginRequest, MarkReadRequest, Meeting, MeetingDetails, MeetingFilter,
+    MeetingSummary, Message, MessagePageQuery, NewInvitation, NewJoinLink, NewMeeting, NewMessage,
+    NewParticipant, NewUser, ParticipantRole, PublicUser, RedeemJoinLinkRequest, RefreshOutcome,
+    RefreshRequest, RegisterRequest, TokenResponse, UpdateMeeting, UpdateUserRoleRequest, User,
+};
+use crate::repository::Repository;
+use crate::room::{GetRoster, RoomRegistry};
 use crate::services::{
-    create_meeting, create_message, create_user, generate_jwt, get_meeting_by_id,
-    get_messages_for_meeting, get_user_by_email, get_user_by_id, hash_password, verify_password,
+    decode_join_token, ensure_can_post, generate_join_token, generate_jwt, generate_refresh_token,
+    hash_password, hash_refresh_token, join_link_expiry, mark_read, message_page_request,
+    not_a_participant, validate_content, validate_meeting_times, validate_message, verify_password,
 };
-use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
-use log::{error, info};
-use serde_json::json;
-
-pub async fn register_handler(users: web::Json<NewUser>) -> impl Responder {
-    info!("Registering new user with email: {}", users.email);
-
-    match hash_password(&users.password) {
-        Ok(hashed_password) => {
-            let new_user = NewUser {
-                name: users.name.clone(),
-                email: users.email.clone(),
-                password: hashed_password,
-            };
-            match create_user(new_user) {
-                Ok(user) => {
-                    info!("User created successfully: {:?}", user);
-                    HttpResponse::Created().json(user)
-                }
-                Err(e) => {
-                    error!("Error creating user: {:?}", e);
-                    HttpResponse::InternalServerError().finish()
-                }
-            }
+use crate::ws::{
+    close_meeting_room, close_revoked_sessions, evict_participant, publish_message,
+    publish_message_change, publish_read,
+};
+use actix::Addr;
+use actix_web::{web, HttpResponse, Responder};
+use chrono::NaiveDateTime;
+use diesel::result::Error as DieselError;
+use diesel::OptionalExtension;
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// Maps a missing row to a 404 naming the resource, and any other database
+/// failure through the usual `ApiError` conversion.
+fn or_not_found(resource: String) -> impl FnOnce(DbError) -> ApiError {
+    move |e| match e {
+        DbError::Query(DieselError::NotFound) => {
+            ApiError::NotFound(format!("{} not found", resource))
         }
-        Err(e) => {
-            error!("Error hashing password: {:?}", e);
-            HttpResponse::InternalServerError().finish()
+        e => e.into(),
+    }
+}
+
+/// Rejects a client-supplied owner id that differs from the caller's own.
+fn check_claimed_id(claimed: Option<i32>, user_id: i32, field: &str) -> Result<(), ApiError> {
+    match claimed {
+        Some(claimed) if claimed != user_id => {
+            info!(
+                user_id,
+                field, claimed, "claimed id does not match the caller"
+            );
+            Err(ApiError::Forbidden(format!(
+                "{} must match the authenticated user",
+                field
+            )))
         }
+        _ => Ok(()),
     }
 }
 
-pub async fn login_handler(body: web::Json<LoginRequest>) -> impl Responder {
+async fn register_user(pool: &DbPool, request: RegisterRequest) -> Result<User, ApiError> {
+    let new_user = NewUser {
+        password: hash_password(&request.password)?,
+        name: request.name,
+        email: request.email,
+    };
+
+    db::run(pool, move |conn| conn.create_user(new_user))
+        .await
+        .map_err(|e| match e {
+            DbError::Query(DieselError::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => ApiError::Conflict("A user with this email already exists".to_string()),
+            e => e.into(),
+        })
+}
+
+/// Replies with the new account as its owner sees it: email included,
+/// password hash left out.
+pub async fn register_handler(
+    pool: web::Data<DbPool>,
+    body: web::Json<RegisterRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let user = register_user(&pool, body.into_inner()).await?;
+    info!(user_id = user.id, "user registered");
+    Ok(HttpResponse::Created().json(user))
+}
+
+pub async fn login_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    metrics: web::Data<Metrics>,
+    body: web::Json<LoginRequest>,
+) -> Result<HttpResponse, ApiError> {
     let login_data = body.into_inner();
-    info!("Attempting login for email: {}", login_data.email);
-
-    match get_user_by_email(&login_data.email) {
-        Ok(existing_user) => {
-            if verify_password(&login_data.password, &existing_user.password).unwrap_or(false) {
-                match generate_jwt(&existing_user.id.to_string()) {
-                    Ok(token) => {
-                        info!(
-                            "Login successful, token generated for user: {}",
-                            login_data.email
-                        );
-                        HttpResponse::Ok().json(json!({ "token": token }))
-                    }
-                    Err(e) => {
-                        error!("Error generating token: {:?}", e);
-                        HttpResponse::InternalServerError()
-                            .json(json!({ "message": "Error generating token" }))
-                    }
-                }
-            } else {
-                info!("Invalid password attempt for email: {}", login_data.email);
-                HttpResponse::Unauthorized().json(json!({ "message": "Invalid password" }))
-            }
+    let email = login_data.email.clone();
+    let existing_user = match db::run(&pool, move |conn| conn.get_user_by_email(&email)).await {
+        Ok(user) => user,
+        Err(DbError::Query(DieselError::NotFound)) => {
+            info!("login failed: unknown email");
+            metrics.auth_failure("bad_credentials");
+            return Err(ApiError::Unauthorized("User not found".to_string()));
         }
-        Err(_) => {
-            info!("User not found for email: {}", login_data.email);
-            HttpResponse::Unauthorized().json(json!({ "message": "User not found" }))
+        Err(e) => return Err(e.into()),
+    };
+
+    if !verify_password(&login_data.password, &existing_user.password).unwrap_or(false) {
+        info!(user_id = existing_user.id, "login failed: wrong password");
+        metrics.auth_failure("bad_credentials");
+        return Err(ApiError::Unauthorized("Invalid password".to_string()));
+    }
+
+    let user_id = existing_user.id;
+    let refresh_token = generate_refresh_token();
+    let token_hash = hash_refresh_token(&refresh_token);
+    let expires_at = refresh_token_expiry(&config.auth);
+    let session = db::run(&pool, move |conn| {
+        conn.create_session(user_id, token_hash, expires_at)
+    })
+    .await?;
+
+    let tokens = token_response(&config.auth, &existing_user, session.id, refresh_token)?;
+    info!(
+        user_id = existing_user.id,
+        session_id = session.id,
+        "login succeeded"
+    );
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+fn refresh_token_expiry(auth: &AuthConfig) -> NaiveDateTime {
+    chrono::Utc::now().naive_utc() + auth.refresh_token_lifetime()
+}
+
+fn token_response(
+    auth: &AuthConfig,
+    user: &User,
+    session_id: i32,
+    refresh_token: String,
+) -> Result<TokenResponse, ApiError> {
+    Ok(TokenResponse {
+        token: generate_jwt(auth, user.id, session_id, user.role)?,
+        token_type: "Bearer",
+        expires_in: auth.access_token_lifetime().num_seconds(),
+        refresh_token,
+    })
+}
+
+/// Trades a refresh token for a new access token and a new refresh token.
+/// The old refresh token stops working; presenting it again revokes the
+/// session, since only a stolen copy would still be using it.
+pub async fn refresh_token_handler(
+    pool: web::Data<DbPool>,
+    rooms: web::Data<Addr<RoomRegistry>>,
+    config: web::Data<Config>,
+    metrics: web::Data<Metrics>,
+    body: web::Json<RefreshRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let token_hash = hash_refresh_token(&body.refresh_token);
+    let refresh_token = generate_refresh_token();
+    let new_token_hash = hash_refresh_token(&refresh_token);
+    let expires_at = refresh_token_expiry(&config.auth);
+    let now = chrono::Utc::now().naive_utc();
+
+    let outcome = db::run(&pool, move |conn| {
+        conn.rotate_refresh_token(&token_hash, new_token_hash, expires_at, now)
+    })
+    .await?;
+
+    if !matches!(outcome, RefreshOutcome::Rotated(_)) {
+        metrics.auth_failure(match outcome {
+            RefreshOutcome::Reused(_) => "refresh_token_reused",
+            _ => "invalid_refresh_token",
+        });
+    }
+
+    match outcome {
+        RefreshOutcome::Rotated(session) => {
+            info!(session_id = session.id, "refresh token rotated");
+            // The role is read again so that role changes reach the new token.
+            let user_id = session.user_id;
+            let user = db::run(&pool, move |conn| conn.get_user_by_id(user_id)).await?;
+            let tokens = token_response(&config.auth, &user, session.id, refresh_token)?;
+            Ok(HttpResponse::Ok().json(tokens))
         }
+        RefreshOutcome::Reused(session) => {
+            warn!(
+                session_id = session.id,
+                user_id = session.user_id,
+                "refresh token reused; session revoked"
+            );
+            close_revoked_sessions(&rooms, session.user_id, Some(session.id));
+            Err(ApiError::Unauthorized(
+                "Refresh token has already been used; please log in again".to_string(),
+            ))
+        }
+        RefreshOutcome::Expired => Err(ApiError::Unauthorized(
+            "Refresh token has expired".to_string(),
+        )),
+        RefreshOutcome::Revoked => Err(ApiError::Unauthorized(
+            "Session has been revoked".to_string(),
+        )),
+        RefreshOutcome::Unknown => Err(ApiError::Unauthorized("Invalid refresh token".to_string())),
     }
 }
 
+/// Ends the session the access token belongs to and closes the WebSockets
+/// opened with it.
+pub async fn logout_handler(
+    pool: web::Data<DbPool>,
+    rooms: web::Data<Addr<RoomRegistry>>,
+    claims: Claims,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = claims.user_id()?;
+    let session_id = claims.sid;
+    let now = chrono::Utc::now().naive_utc();
+    db::run(&pool, move |conn| conn.revoke_session(session_id, now)).await?;
+    close_revoked_sessions(&rooms, user_id, Some(session_id));
+    info!(session_id, "session logged out");
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Ends every session of the caller, on all devices, and closes their
+/// WebSockets.
+pub async fn logout_all_handler(
+    pool: web::Data<DbPool>,
+    rooms: web::Data<Addr<RoomRegistry>>,
+    claims: Claims,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = claims.user_id()?;
+    let now = chrono::Utc::now().naive_utc();
+    let revoked = db::run(&pool, move |conn| conn.revoke_user_sessions(user_id, now)).await?;
+    close_revoked_sessions(&rooms, user_id, None);
+    info!(user_id, count = revoked, "all sessions logged out");
+    Ok(HttpResponse::NoContent().finish())
+}
+
 pub async fn index() -> impl Responder {
-    info!("Index route called");
     HttpResponse::Ok().body("Welcome to the Convo server!")
 }
 
+/// Liveness: the process is up and serving requests.
+pub async fn healthz_handler() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Readiness: a pooled database connection answers a query.
+pub async fn readyz_handler(pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    db::run(&pool, |conn| conn.ping()).await.map_err(|e| {
+        warn!(error = %e, "readiness check failed");
+        ApiError::ServiceUnavailable("Database unavailable".to_string())
+    })?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "ok", "database": "ok" })))
+}
+
+pub async fn metrics_handler(
+    pool: web::Data<DbPool>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse, ApiError> {
+    let body = metrics
+        .render(&pool)
+        .map_err(|e| ApiError::Internal(format!("failed to encode metrics: {}", e)))?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/openmetrics-text; version=1.0.0; charset=utf-8")
+        .body(body))
+}
+
 pub async fn api_handler(req_body: String) -> impl Responder {
-    info!("API handler received request body: {}", req_body);
+    info!(body_len = req_body.len(), "api handler called");
     HttpResponse::Ok().body(format!("Received: {}", req_body))
 }
 
-pub async fn get_user(req: HttpRequest) -> impl Responder {
-    if let Some(claims) = req.extensions().get::<Claims>() {
-        let user_id = claims.sub.parse::<i32>().unwrap_or(0);
-        info!("Fetching user with ID: {}", user_id);
+/// Returns the caller's own account, email included, or only the public
+/// fields of anyone else's.
+pub async fn get_user(
+    pool: web::Data<DbPool>,
+    claims: Claims,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = path.into_inner();
+    let user = db::run(&pool, move |conn| conn.get_user_by_id(user_id))
+        .await
+        .map_err(or_not_found(format!("User {}", user_id)))?;
+    if user.id == claims.user_id()? {
+        Ok(HttpResponse::Ok().json(user))
+    } else {
+        Ok(HttpResponse::Ok().json(PublicUser::from(user)))
+    }
+}
+
+/// Changes a user's server-wide role. Admins only; the change reaches the
+/// user's access token on its next refresh.
+pub async fn update_user_role_handler(
+    pool: web::Data<DbPool>,
+    admin: RequireRole<Admin>,
+    path: web::Path<i32>,
+    body: web::Json<UpdateUserRoleRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = path.into_inner();
+    let role = body.role;
+    info!(
+        admin_id = admin.claims.sub,
+        user_id,
+        role = role.as_str(),
+        "setting user role"
+    );
 
-        match get_user_by_id(user_id) {
-            Ok(user) => {
-                info!("User fetched successfully: {:?}", user);
-                HttpResponse::Ok().json(user)
-            }
-            Err(e) => {
-                error!("Error fetching user: {:?}", e);
-                HttpResponse::InternalServerError().finish()
+    let user = db::run(&pool, move |conn| conn.set_user_role(user_id, role))
+        .await
+        .map_err(or_not_found(format!("User {}", user_id)))?;
+    Ok(HttpResponse::Ok().json(PublicUser::from(user)))
+}
+
+/// Creates an account on someone else's behalf. Admins only; anyone else
+/// signs up through `/register`.
+pub async fn create_user_handler(
+    pool: web::Data<DbPool>,
+    admin: RequireRole<Admin>,
+    body: web::Json<RegisterRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let user = register_user(&pool, body.into_inner()).await?;
+    info!(
+        admin_id = admin.claims.sub,
+        user_id = user.id,
+        "user created"
+    );
+    Ok(HttpResponse::Created().json(PublicUser::from(user)))
+}
+
+pub async fn create_meeting_handler(
+    pool: web::Data<DbPool>,
+    member: RequireRole<Member>,
+    meeting: web::Json<CreateMeetingRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = member.claims.user_id()?;
+    let request = meeting.into_inner();
+    check_claimed_id(request.creator_id, user_id, "creator_id")?;
+
+    let new_meeting = NewMeeting {
+        title: request.title,
+        description: request.description,
+        start_time: request.start_time,
+        end_time: request.end_time,
+        creator_id: user_id,
+    };
+    validate_meeting_times(new_meeting.start_time, new_meeting.end_time)?;
+    let meeting = db::run(&pool, move |conn| conn.create_meeting(new_meeting)).await?;
+    info!(meeting_id = meeting.id, "meeting created");
+    Ok(HttpResponse::Created().json(meeting))
+}
+
+pub async fn get_meeting_handler(
+    pool: web::Data<DbPool>,
+    access: MeetingAccess<View>,
+) -> Result<HttpResponse, ApiError> {
+    let meeting_id = access.meeting_id;
+    let meeting = db::run(&pool, move |conn| {
+        let meeting = conn.get_meeting_by_id(meeting_id)?;
+        let participants = conn.list_participants(meeting_id)?;
+        Ok(MeetingDetails {
+            meeting,
+            participants,
+        })
+    })
+    .await
+    .map_err(or_not_found(format!("Meeting {}", meeting_id)))?;
+    Ok(HttpResponse::Ok().json(meeting))
+}
+
+pub async fn list_meetings_handler(
+    pool: web::Data<DbPool>,
+    claims: Option<Claims>,
+    filter: web::Query<MeetingFilter>,
+) -> Result<HttpResponse, ApiError> {
+    // Unread counts need a caller, which public routes may not have.
+    let user_id = claims.map(|claims| claims.user_id()).transpose()?;
+    let filter = filter.into_inner();
+    let now = chrono::Utc::now().naive_utc();
+    let (meetings, mut unread) = db::run(&pool, move |conn| {
+        let meetings = conn.list_meetings(&filter, now)?;
+        let unread = match user_id {
+            Some(user_id) => {
+                let ids: Vec<i32> = meetings.iter().map(|meeting| meeting.id).collect();
+                conn.unread_counts(user_id, &ids)?
             }
-        }
-    } else {
-        info!("Unauthorized access attempt");
-        HttpResponse::Unauthorized().finish()
+            None => HashMap::new(),
+        };
+        Ok((meetings, unread))
+    })
+    .await?;
+    info!(count = meetings.len(), "meetings listed");
+    let meetings: Vec<MeetingSummary> = meetings
+        .into_iter()
+        .map(|meeting| MeetingSummary {
+            unread_count: unread.remove(&meeting.id),
+            meeting,
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(meetings))
+}
+
+/// Loads a meeting and checks that the caller created it.
+async fn owned_meeting(
+    pool: &DbPool,
+    claims: &Claims,
+    meeting_id: i32,
+) -> Result<Meeting, ApiError> {
+    let user_id = claims.user_id()?;
+    let meeting = db::run(pool, move |conn| conn.get_meeting_by_id(meeting_id))
+        .await
+        .map_err(or_not_found(format!("Meeting {}", meeting_id)))?;
+
+    if meeting.creator_id != user_id {
+        info!(user_id, meeting_id, "caller does not own the meeting");
+        return Err(ApiError::Forbidden(
+            "Only the meeting creator can modify this meeting".to_string(),
+        ));
     }
+    Ok(meeting)
 }
 
-pub async fn create_user_handler(new_user: web::Json<NewUser>) -> impl Responder {
-    info!("Creating user with data: {:?}", new_user);
+pub async fn update_meeting_handler(
+    pool: web::Data<DbPool>,
+    claims: Claims,
+    path: web::Path<i32>,
+    changes: web::Json<UpdateMeeting>,
+) -> Result<HttpResponse, ApiError> {
+    let meeting_id = path.into_inner();
+    let changes = changes.into_inner();
+    if changes.is_empty() {
+        return Err(ApiError::Validation("No fields to update".to_string()));
+    }
+    if matches!(&changes.title, Some(title) if title.trim().is_empty()) {
+        return Err(ApiError::Validation(
+            "Meeting title must not be empty".to_string(),
+        ));
+    }
 
-    match create_user(new_user.into_inner()) {
-        Ok(user) => {
-            info!("User created successfully: {:?}", user);
-            HttpResponse::Created().json(user)
-        }
-        Err(e) => {
-            error!("Error creating user: {:?}", e);
-            HttpResponse::InternalServerError().finish()
-        }
+    let meeting = owned_meeting(&pool, &claims, meeting_id).await?;
+    validate_meeting_times(
+        changes.start_time.unwrap_or(meeting.start_time),
+        changes.end_time.unwrap_or(meeting.end_time),
+    )?;
+
+    let meeting = db::run(&pool, move |conn| conn.update_meeting(meeting_id, changes)).await?;
+    info!(meeting_id, "meeting updated");
+    Ok(HttpResponse::Ok().json(meeting))
+}
+
+pub async fn delete_meeting_handler(
+    pool: web::Data<DbPool>,
+    rooms: web::Data<Addr<RoomRegistry>>,
+    claims: Claims,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let meeting_id = path.into_inner();
+    owned_meeting(&pool, &claims, meeting_id).await?;
+    db::run(&pool, move |conn| conn.delete_meeting(meeting_id)).await?;
+    close_meeting_room(&rooms, meeting_id);
+    info!(meeting_id, "meeting deleted along with its messages");
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Looks up the caller's role in a meeting, failing with 403 if they are
+/// not a participant.
+async fn participant_role(
+    pool: &DbPool,
+    meeting_id: i32,
+    user_id: i32,
+) -> Result<ParticipantRole, ApiError> {
+    db::run(pool, move |conn| conn.get_participant(meeting_id, user_id))
+        .await
+        .map(|participant| participant.role)
+        .map_err(not_a_participant)
+}
+
+/// The role a new participant is given; there is only ever one host.
+fn granted_role(role: Option<ParticipantRole>) -> Result<ParticipantRole, ApiError> {
+    match role.unwrap_or(ParticipantRole::Attendee) {
+        ParticipantRole::Host => Err(ApiError::Validation(
+            "A meeting has exactly one host".to_string(),
+        )),
+        role => Ok(role),
+    }
+}
+
+pub async fn add_participant_handler(
+    pool: web::Data<DbPool>,
+    access: MeetingAccess<Manage>,
+    body: web::Json<AddParticipantRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let meeting_id = access.meeting_id;
+    let request = body.into_inner();
+
+    let role = granted_role(request.role)?;
+
+    let user_id = request.user_id;
+    let new_participant = NewParticipant {
+        meeting_id,
+        user_id,
+        role,
+    };
+    let participant = db::run(&pool, move |conn| conn.add_participant(new_participant))
+        .await
+        .map_err(|e| match e {
+            DbError::Query(DieselError::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => ApiError::Conflict(format!("User {} is already a participant", user_id)),
+            DbError::Query(DieselError::DatabaseError(
+                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                _,
+            )) => ApiError::NotFound(format!("User {} not found", user_id)),
+            e => e.into(),
+        })?;
+    info!(meeting_id, user_id, "participant added");
+    Ok(HttpResponse::Created().json(participant))
+}
+
+/// Removes a participant. Hosts and co-hosts may remove anyone but the host;
+/// everyone else may only remove themselves.
+pub async fn remove_participant_handler(
+    pool: web::Data<DbPool>,
+    claims: Claims,
+    path: web::Path<(i32, i32)>,
+    rooms: web::Data<Addr<RoomRegistry>>,
+) -> Result<HttpResponse, ApiError> {
+    let (meeting_id, user_id) = path.into_inner();
+    let caller_id = claims.user_id()?;
+    let caller_role = participant_role(&pool, meeting_id, caller_id).await?;
+    if caller_id != user_id && !caller_role.can_manage() {
+        return Err(ApiError::Forbidden(
+            "Only hosts and co-hosts can manage participants".to_string(),
+        ));
     }
+
+    let target = db::run(&pool, move |conn| conn.get_participant(meeting_id, user_id))
+        .await
+        .map_err(or_not_found(format!("Participant {}", user_id)))?;
+    if target.role == ParticipantRole::Host {
+        return Err(ApiError::Validation(
+            "The host cannot be removed from the meeting".to_string(),
+        ));
+    }
+
+    db::run(&pool, move |conn| {
+        conn.remove_participant(meeting_id, user_id)
+    })
+    .await?;
+    evict_participant(&rooms, meeting_id, user_id);
+    info!(meeting_id, user_id, "participant removed");
+    Ok(HttpResponse::NoContent().finish())
 }
 
-pub async fn create_meeting_handler(meeting: web::Json<NewMeeting>) -> impl Responder {
-    info!("Creating meeting with data: {:?}", meeting);
+/// Who is connected to a meeting's WebSocket room, including sessions that
+/// dropped and may still reconnect.
+pub async fn get_presence_handler(
+    rooms: web::Data<Addr<RoomRegistry>>,
+    access: MeetingAccess<View>,
+) -> Result<HttpResponse, ApiError> {
+    let meeting_id = access.meeting_id;
+    let roster = rooms
+        .send(GetRoster { meeting_id })
+        .await
+        .map_err(|e| ApiError::Internal(format!("room registry unavailable: {}", e)))?;
+    Ok(HttpResponse::Ok().json(roster))
+}
+
+pub async fn create_invitation_handler(
+    pool: web::Data<DbPool>,
+    access: MeetingAccess<Manage>,
+    body: web::Json<CreateInvitationRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let meeting_id = access.meeting_id;
+    let request = body.into_inner();
+    let inviter_id = access.claims.user_id()?;
+    let role = granted_role(request.role)?;
 
-    match create_meeting(meeting.into_inner()) {
-        Ok(meeting) => {
-            info!("Meeting created successfully: {:?}", meeting);
-            HttpResponse::Created().json(meeting)
+    let (invitee_user_id, invitee_email) = match (request.user_id, request.email) {
+        (Some(user_id), None) => (Some(user_id), None),
+        (None, Some(email)) => {
+            let email = email.trim().to_string();
+            if !email.contains('@') {
+                return Err(ApiError::Validation("Invalid email address".to_string()));
+            }
+            (None, Some(email))
         }
-        Err(e) => {
-            error!("Failed to create meeting: {:?}", e);
-            HttpResponse::InternalServerError().body("Failed to create meeting")
+        _ => {
+            return Err(ApiError::Validation(
+                "Give exactly one of user_id and email".to_string(),
+            ))
+        }
+    };
+
+    if let Some(user_id) = invitee_user_id {
+        let existing = db::run(&pool, move |conn| {
+            conn.get_user_by_id(user_id)?;
+            conn.get_participant(meeting_id, user_id).optional()
+        })
+        .await
+        .map_err(or_not_found(format!("User {}", user_id)))?;
+        if existing.is_some() {
+            return Err(ApiError::Conflict(format!(
+                "User {} is already a participant",
+                user_id
+            )));
         }
     }
+
+    let new_invitation = NewInvitation {
+        meeting_id,
+        inviter_id,
+        invitee_user_id,
+        invitee_email,
+        role,
+    };
+    let invitation = db::run(&pool, move |conn| conn.create_invitation(new_invitation)).await?;
+    info!(
+        meeting_id,
+        invitation_id = invitation.id,
+        "invitation created"
+    );
+    Ok(HttpResponse::Created().json(invitation))
 }
 
-pub async fn get_meeting_handler(path: web::Path<i32>) -> impl Responder {
-    let meeting_id = path.into_inner();
-    info!("Fetching meeting with ID: {}", meeting_id);
+pub async fn list_invitations_handler(
+    pool: web::Data<DbPool>,
+    claims: Claims,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = claims.user_id()?;
+    let invitations = db::run(&pool, move |conn| {
+        let user = conn.get_user_by_id(user_id)?;
+        conn.list_pending_invitations(&user)
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(invitations))
+}
+
+/// Loads an invitation addressed to the caller that is still pending.
+/// Invitations meant for someone else are reported as missing.
+async fn pending_invitation(
+    pool: &DbPool,
+    claims: &Claims,
+    invitation_id: i32,
+) -> Result<(), ApiError> {
+    let user_id = claims.user_id()?;
+    let (user, invitation) = db::run(pool, move |conn| {
+        Ok((
+            conn.get_user_by_id(user_id)?,
+            conn.get_invitation(invitation_id)?,
+        ))
+    })
+    .await
+    .map_err(or_not_found(format!("Invitation {}", invitation_id)))?;
+
+    if !invitation.is_addressed_to(&user) {
+        return Err(ApiError::NotFound(format!(
+            "Invitation {} not found",
+            invitation_id
+        )));
+    }
+    if invitation.status != InvitationStatus::Pending {
+        return Err(ApiError::Conflict(format!(
+            "Invitation {} has already been {}",
+            invitation_id,
+            invitation.status.as_str()
+        )));
+    }
+    Ok(())
+}
 
-    match get_meeting_by_id(meeting_id) {
-        Ok(meeting) => {
-            info!("Meeting fetched successfully: {:?}", meeting);
-            HttpResponse::Ok().json(meeting)
+pub async fn accept_invitation_handler(
+    pool: web::Data<DbPool>,
+    claims: Claims,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let invitation_id = path.into_inner();
+    pending_invitation(&pool, &claims, invitation_id).await?;
+    let user_id = claims.user_id()?;
+    let participant = db::run(&pool, move |conn| {
+        conn.accept_invitation(invitation_id, user_id)
+    })
+    .await
+    .map_err(|e| match e {
+        DbError::Query(DieselError::NotFound) => {
+            ApiError::Conflict(format!("Invitation {} is no longer pending", invitation_id))
         }
-        Err(e) => {
-            error!("Error fetching meeting: {:?}", e);
-            HttpResponse::NotFound().finish()
+        e => e.into(),
+    })?;
+    info!(invitation_id, user_id, "invitation accepted");
+    Ok(HttpResponse::Ok().json(participant))
+}
+
+pub async fn decline_invitation_handler(
+    pool: web::Data<DbPool>,
+    claims: Claims,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let invitation_id = path.into_inner();
+    pending_invitation(&pool, &claims, invitation_id).await?;
+    let invitation = db::run(&pool, move |conn| conn.decline_invitation(invitation_id))
+        .await
+        .map_err(|e| match e {
+            DbError::Query(DieselError::NotFound) => {
+                ApiError::Conflict(format!("Invitation {} is no longer pending", invitation_id))
+            }
+            e => e.into(),
+        })?;
+    Ok(HttpResponse::Ok().json(invitation))
+}
+
+fn join_link_response(auth: &AuthConfig, link: JoinLink) -> Result<JoinLinkResponse, ApiError> {
+    let token = generate_join_token(auth, &link)?;
+    Ok(JoinLinkResponse {
+        has_passcode: link.passcode_hash.is_some(),
+        url: format!("/join/{}", token),
+        token,
+        link,
+    })
+}
+
+pub async fn create_join_link_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    access: MeetingAccess<Manage>,
+    body: web::Json<CreateJoinLinkRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let meeting_id = access.meeting_id;
+    let request = body.into_inner();
+
+    let user_id = access.claims.user_id()?;
+    let role = granted_role(request.role)?;
+    if matches!(request.max_uses, Some(max_uses) if max_uses < 1) {
+        return Err(ApiError::Validation(
+            "max_uses must be at least 1".to_string(),
+        ));
+    }
+    let passcode_hash = match request.passcode {
+        Some(passcode) if passcode.is_empty() => {
+            return Err(ApiError::Validation(
+                "Passcode must not be empty".to_string(),
+            ))
         }
+        Some(passcode) => Some(hash_password(&passcode)?),
+        None => None,
+    };
+    let expires_at = join_link_expiry(request.expires_in_hours, chrono::Utc::now().naive_utc())?;
+
+    let new_link = NewJoinLink {
+        meeting_id,
+        created_by: user_id,
+        role,
+        max_uses: request.max_uses,
+        passcode_hash,
+        expires_at,
+    };
+    let link = db::run(&pool, move |conn| conn.create_join_link(new_link)).await?;
+    info!(meeting_id, link_id = link.id, "join link created");
+    Ok(HttpResponse::Created().json(join_link_response(&config.auth, link)?))
+}
+
+pub async fn list_join_links_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    access: MeetingAccess<Manage>,
+) -> Result<HttpResponse, ApiError> {
+    let meeting_id = access.meeting_id;
+    let links = db::run(&pool, move |conn| conn.list_join_links(meeting_id)).await?;
+    let links = links
+        .into_iter()
+        .map(|link| join_link_response(&config.auth, link))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(HttpResponse::Ok().json(links))
+}
+
+pub async fn revoke_join_link_handler(
+    pool: web::Data<DbPool>,
+    _access: MeetingAccess<Manage>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, ApiError> {
+    let (meeting_id, link_id) = path.into_inner();
+    let link = db::run(&pool, move |conn| conn.get_join_link(link_id))
+        .await
+        .map_err(or_not_found(format!("Join link {}", link_id)))?;
+    if link.meeting_id != meeting_id {
+        return Err(ApiError::NotFound(format!(
+            "Join link {} not found",
+            link_id
+        )));
     }
+
+    let now = chrono::Utc::now().naive_utc();
+    db::run(&pool, move |conn| conn.revoke_join_link(link_id, now)).await?;
+    info!(meeting_id, link_id, "join link revoked");
+    Ok(HttpResponse::NoContent().finish())
 }
 
-pub async fn create_message_handler(message: web::Json<NewMessage>) -> impl Responder {
-    info!("Creating message with data: {:?}", message);
+/// Adds the caller to the meeting behind a join link. Redeeming a link for
+/// a meeting the caller already belongs to does not use it up.
+pub async fn redeem_join_link_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    claims: Claims,
+    path: web::Path<String>,
+    body: Option<web::Json<RedeemJoinLinkRequest>>,
+) -> Result<HttpResponse, ApiError> {
+    let link_claims = decode_join_token(&config.auth, &path.into_inner())?;
+    let link_id = link_claims.link_id;
+    let user_id = claims.user_id()?;
+    let link = db::run(&pool, move |conn| conn.get_join_link(link_id))
+        .await
+        .map_err(|e| match e {
+            DbError::Query(DieselError::NotFound) => {
+                ApiError::NotFound("Join link is invalid or has expired".to_string())
+            }
+            e => e.into(),
+        })?;
+    if link.meeting_id != link_claims.meeting_id || link.revoked_at.is_some() {
+        return Err(ApiError::NotFound(
+            "Join link is invalid or has expired".to_string(),
+        ));
+    }
 
-    match create_message(message.into_inner()) {
-        Ok(message) => {
-            info!("Message created successfully: {:?}", message);
-            HttpResponse::Created().json(message)
-        }
-        Err(e) => {
-            error!("Failed to create message: {:?}", e);
-            HttpResponse::InternalServerError().finish()
+    if let Some(passcode_hash) = &link.passcode_hash {
+        let passcode = body.and_then(|body| body.into_inner().passcode);
+        let matches = passcode
+            .map(|passcode| verify_password(&passcode, passcode_hash).unwrap_or(false))
+            .unwrap_or(false);
+        if !matches {
+            info!(link_id, "wrong join link passcode");
+            return Err(ApiError::Forbidden("Incorrect passcode".to_string()));
         }
     }
+
+    let meeting_id = link.meeting_id;
+    let existing = db::run(&pool, move |conn| {
+        conn.get_participant(meeting_id, user_id).optional()
+    })
+    .await?;
+    if let Some(participant) = existing {
+        return Ok(HttpResponse::Ok().json(participant));
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let participant = db::run(&pool, move |conn| {
+        conn.redeem_join_link(link_id, user_id, now)
+    })
+    .await?
+    .ok_or_else(|| {
+        ApiError::Forbidden(
+            "Join link has expired or reached its maximum number of uses".to_string(),
+        )
+    })?;
+    info!(user_id, meeting_id, link_id, "joined meeting via link");
+    Ok(HttpResponse::Created().json(participant))
+}
+
+pub async fn create_message_handler(
+    pool: web::Data<DbPool>,
+    claims: Claims,
+    message: web::Json<CreateMessageRequest>,
+    rooms: web::Data<Addr<RoomRegistry>>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = claims.user_id()?;
+    let request = message.into_inner();
+    check_claimed_id(request.sender_id, user_id, "sender_id")?;
+
+    let new_message = NewMessage {
+        content: request.content,
+        sender_id: user_id,
+        meeting_id: request.meeting_id,
+        timestamp: chrono::Utc::now().naive_utc(),
+    };
+    validate_message(&new_message)?;
+    ensure_can_post(participant_role(&pool, new_message.meeting_id, user_id).await?)?;
+
+    let message = db::run(&pool, move |conn| conn.create_message(new_message)).await?;
+    info!(
+        meeting_id = message.meeting_id,
+        message_id = message.id,
+        "message created"
+    );
+    metrics.message_stored("http");
+    publish_message(&rooms, &message);
+    Ok(HttpResponse::Created().json(message))
+}
+
+pub async fn get_messages_handler(
+    pool: web::Data<DbPool>,
+    access: MeetingAccess<View>,
+    query: web::Query<MessagePageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let meeting_id = access.meeting_id;
+    let page = message_page_request(query.before.as_deref(), query.after.as_deref(), query.limit)?;
+    let page = db::run(&pool, move |conn| conn.get_message_page(meeting_id, &page)).await?;
+    info!(meeting_id, count = page.messages.len(), "messages fetched");
+    Ok(HttpResponse::Ok().json(page))
+}
+
+/// Loads a message of the meeting that has not been deleted.
+async fn live_message(
+    pool: &DbPool,
+    meeting_id: i32,
+    message_id: i32,
+) -> Result<Message, ApiError> {
+    let message = db::run(pool, move |conn| conn.get_message(meeting_id, message_id))
+        .await
+        .map_err(or_not_found(format!("Message {}", message_id)))?;
+    if message.deleted_at.is_some() {
+        return Err(ApiError::Conflict(format!(
+            "Message {} has been deleted",
+            message_id
+        )));
+    }
+    Ok(message)
 }
 
-pub async fn get_messages_handler(path: web::Path<i32>) -> impl Responder {
-    let meeting_id = path.into_inner();
-    info!("Fetching messages for meeting with ID: {}", meeting_id);
+/// Replaces the content of the caller's own message, keeping the old
+/// content as a revision.
+pub async fn edit_message_handler(
+    pool: web::Data<DbPool>,
+    rooms: web::Data<Addr<RoomRegistry>>,
+    access: MeetingAccess<Post>,
+    path: web::Path<(i32, i32)>,
+    body: web::Json<EditMessageRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let (meeting_id, message_id) = path.into_inner();
+    let user_id = access.claims.user_id()?;
+    let content = body.into_inner().content;
+    validate_content(&content)?;
 
-    match get_messages_for_meeting(meeting_id) {
-        Ok(messages) => {
-            info!(
-                "Messages fetched successfully for meeting ID: {}",
-                meeting_id
-            );
-            HttpResponse::Ok().json(messages)
+    let message = live_message(&pool, meeting_id, message_id).await?;
+    if message.sender_id != user_id {
+        return Err(ApiError::Forbidden(
+            "Only the author can edit a message".to_string(),
+        ));
+    }
+    let now = chrono::Utc::now().naive_utc();
+    let message = db::run(&pool, move |conn| {
+        conn.edit_message(message_id, user_id, &content, now)
+    })
+    .await
+    .map_err(|e| match e {
+        DbError::Query(DieselError::NotFound) => {
+            ApiError::Conflict(format!("Message {} has been deleted", message_id))
         }
-        Err(e) => {
-            error!(
-                "Error fetching messages for meeting ID: {}: {:?}",
-                meeting_id, e
-            );
-            HttpResponse::InternalServerError().finish()
+        e => e.into(),
+    })?;
+    info!(meeting_id, message_id, "message edited");
+    publish_message_change(&rooms, &message);
+    Ok(HttpResponse::Ok().json(message))
+}
+
+/// Deletes a message, leaving a tombstone in its place. Authors who may
+/// still post can delete their own messages, and hosts and co-hosts anyone's.
+pub async fn delete_message_handler(
+    pool: web::Data<DbPool>,
+    rooms: web::Data<Addr<RoomRegistry>>,
+    access: MeetingAccess<Post>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, ApiError> {
+    let (meeting_id, message_id) = path.into_inner();
+    let user_id = access.claims.user_id()?;
+
+    let message = live_message(&pool, meeting_id, message_id).await?;
+    if message.sender_id != user_id && !access.role.can_manage() {
+        return Err(ApiError::Forbidden(
+            "Only the author, hosts and co-hosts can delete a message".to_string(),
+        ));
+    }
+    let now = chrono::Utc::now().naive_utc();
+    let message = db::run(&pool, move |conn| {
+        conn.delete_message(message_id, user_id, now)
+    })
+    .await
+    .map_err(|e| match e {
+        DbError::Query(DieselError::NotFound) => {
+            ApiError::Conflict(format!("Message {} has been deleted", message_id))
         }
+        e => e.into(),
+    })?;
+    info!(meeting_id, message_id, "message deleted");
+    publish_message_change(&rooms, &message);
+    Ok(HttpResponse::Ok().json(message))
+}
+
+/// The earlier contents of a message, oldest first. Visible to its author
+/// and to the meeting's hosts and co-hosts.
+pub async fn list_message_revisions_handler(
+    pool: web::Data<DbPool>,
+    access: MeetingAccess<View>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, ApiError> {
+    let (meeting_id, message_id) = path.into_inner();
+    let user_id = access.claims.user_id()?;
+
+    let message = db::run(&pool, move |conn| conn.get_message(meeting_id, message_id))
+        .await
+        .map_err(or_not_found(format!("Message {}", message_id)))?;
+    if message.sender_id != user_id && !access.role.can_manage() {
+        return Err(ApiError::Forbidden(
+            "Only the author, hosts and co-hosts can see a message's history".to_string(),
+        ));
+    }
+    let revisions = db::run(&pool, move |conn| conn.list_message_revisions(message_id)).await?;
+    Ok(HttpResponse::Ok().json(revisions))
+}
+
+/// Marks every message up to `message_id` as read by the caller and tells
+/// the meeting's live sessions.
+pub async fn mark_read_handler(
+    pool: web::Data<DbPool>,
+    rooms: web::Data<Addr<RoomRegistry>>,
+    access: MeetingAccess<View>,
+    body: web::Json<MarkReadRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let meeting_id = access.meeting_id;
+    let user_id = access.claims.user_id()?;
+    let receipt = mark_read(&pool, meeting_id, user_id, body.message_id).await?;
+    info!(
+        meeting_id,
+        message_id = receipt.last_read_message_id,
+        "messages marked read"
+    );
+    publish_read(&rooms, &receipt);
+    Ok(HttpResponse::Ok().json(receipt))
+}
+
+pub async fn list_read_receipts_handler(
+    pool: web::Data<DbPool>,
+    access: MeetingAccess<View>,
+) -> Result<HttpResponse, ApiError> {
+    let meeting_id = access.meeting_id;
+    let receipts = db::run(&pool, move |conn| conn.list_read_receipts(meeting_id)).await?;
+    Ok(HttpResponse::Ok().json(receipts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::UserRole;
+    use actix::Actor;
+    use actix_web::dev::Service;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App, HttpMessage};
+    use diesel::pg::PgConnection;
+    use diesel::r2d2::{ConnectionManager, Pool};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const CALLER: i32 = 7;
+
+    /// Posts `body` to `path` as user `CALLER`. The pool points nowhere, so
+    /// any request that reaches the database fails with 503.
+    async fn post_as_caller(path: &str, body: Value) -> (StatusCode, Value) {
+        let metrics = Arc::new(Metrics::default());
+        let rooms = RoomRegistry::new(Arc::clone(&metrics), Duration::ZERO).start();
+        let pool: DbPool = Pool::builder()
+            .connection_timeout(Duration::from_millis(100))
+            .build_unchecked(ConnectionManager::<PgConnection>::new(
+                "postgres://localhost:1/unreachable",
+            ));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(rooms))
+                .app_data(web::Data::from(metrics))
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(Claims {
+                        sub: CALLER.to_string(),
+                        sid: 1,
+                        role: UserRole::Member,
+                        exp: usize::MAX,
+                    });
+                    srv.call(req)
+                })
+                .route("/api/meetings", web::post().to(create_meeting_handler))
+                .route("/user", web::post().to(create_user_handler))
+                .route("/api/messages", web::post().to(create_message_handler)),
+        )
+        .await;
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri(path)
+                .set_json(body)
+                .to_request(),
+        )
+        .await;
+        let status = res.status();
+        (status, test::read_body_json(res).await)
+    }
+
+    #[actix_web::test]
+    async fn messages_cannot_claim_another_sender() {
+        let (status, body) = post_as_caller(
+            "/api/messages",
+            json!({ "content": "hi", "meeting_id": 1, "sender_id": CALLER + 1 }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            body,
+            json!({
+                "code": "forbidden",
+                "message": "sender_id must match the authenticated user",
+            })
+        );
+    }
+
+    #[actix_web::test]
+    async fn meetings_cannot_claim_another_creator() {
+        let (status, body) = post_as_caller(
+            "/api/meetings",
+            json!({
+                "title": "Standup",
+                "start_time": "2024-09-07T10:00:00",
+                "end_time": "2024-09-07T11:00:00",
+                "creator_id": CALLER + 1,
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            body["message"],
+            "creator_id must match the authenticated user"
+        );
+    }
+
+    #[actix_web::test]
+    async fn matching_sender_id_gets_past_the_check() {
+        let (status, _) = post_as_caller(
+            "/api/messages",
+            json!({ "content": "hi", "meeting_id": 1, "sender_id": CALLER }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[actix_web::test]
+    async fn only_admins_create_users() {
+        let (status, body) = post_as_caller(
+            "/user",
+            json!({ "name": "dave", "email": "dave@example.com", "password": "password123" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["required"], "admin");
     }
 }
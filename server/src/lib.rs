@@ -0,0 +1,14 @@
+pub mod config;
+pub mod db;
+pub mod errors;
+pub mod guards;
+pub mod handlers;
+pub mod metrics;
+pub mod middleware;
+pub mod models;
+pub mod repository;
+pub mod room;
+pub mod schema;
+pub mod services;
+pub mod telemetry;
+pub mod ws;
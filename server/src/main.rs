@@ -1,52 +1,184 @@
-use crate::middleware::AuthMiddleware;
+use actix::{Actor, Addr};
 use actix_cors::Cors;
+use actix_web::dev::ServerHandle;
 use actix_web::{web, App, HttpServer};
 use dotenv::dotenv;
-use env_logger;
+use server::config::{Cli, Command, Config, ConfigError, USAGE};
+use server::metrics::{Metrics, RequestMetrics};
+use server::middleware::{AuthMiddleware, PublicRoutes};
+use server::room::{RoomRegistry, Shutdown};
+use server::telemetry::{self, RequestTracing, REQUEST_ID_HEADER};
+use server::{db, errors, handlers, ws};
 use std::env;
+use std::sync::Arc;
 
-mod handlers;
-mod middleware;
-mod models;
-mod schema;
-mod services;
-mod utils;
-mod ws;
+fn apply_migrations(pool: &db::DbPool) -> std::io::Result<()> {
+    let mut conn = pool.get().map_err(|e| {
+        tracing::error!(error = %e, "failed to connect to the database for migrations");
+        std::io::Error::new(std::io::ErrorKind::ConnectionRefused, e)
+    })?;
+
+    let applied = db::run_migrations(&mut conn).map_err(|e| {
+        tracing::error!(error = %e, "migrations failed");
+        std::io::Error::other(e)
+    })?;
+    if applied.is_empty() {
+        tracing::info!("database schema is up to date");
+    } else {
+        tracing::info!(migrations = %applied.join(", "), "applied migrations");
+    }
+    Ok(())
+}
+
+/// Resolves on SIGTERM or Ctrl-C.
+async fn shutdown_signal() {
+    let ctrl_c = Box::pin(async {
+        if let Err(e) = actix_web::rt::signal::ctrl_c().await {
+            tracing::error!(error = %e, "cannot listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    });
+
+    #[cfg(unix)]
+    {
+        use actix_web::rt::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                let terminate = Box::pin(async move {
+                    terminate.recv().await;
+                });
+                futures::future::select(ctrl_c, terminate).await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "cannot listen for SIGTERM");
+                ctrl_c.await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    ctrl_c.await;
+}
+
+/// Stops accepting connections once a shutdown signal arrives, asks every
+/// WebSocket session to close, then waits for in-flight requests until
+/// `server.shutdown_timeout_secs` runs out.
+async fn drain_on_shutdown(server: ServerHandle, rooms: Addr<RoomRegistry>) {
+    shutdown_signal().await;
+    tracing::info!("shutdown requested; draining connections");
+
+    // The stop command is sent right away; awaiting it waits for the drain.
+    let stopped = server.stop(true);
+    match rooms
+        .send(Shutdown {
+            reason: "server restarting".to_string(),
+        })
+        .await
+    {
+        Ok(sessions) => tracing::info!(sessions, "asked websocket sessions to close"),
+        Err(e) => tracing::error!(error = %e, "cannot close websocket sessions"),
+    }
+    stopped.await;
+}
+
+fn exit_with(e: ConfigError) -> ! {
+    eprintln!("{}", e);
+    std::process::exit(2)
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    env_logger::init();
 
-    log::info!("Starting server...");
+    // Logging is configured from the config itself, so problems loading it
+    // go straight to stderr.
+    let cli = Cli::parse(env::args().skip(1)).unwrap_or_else(|e| exit_with(e));
+    if cli.command == Command::Help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let config = Config::load(&cli, |key| env::var(key).ok()).unwrap_or_else(|e| exit_with(e));
+    telemetry::init(&config.log);
+    tracing::info!(server = ?config.server, "loaded configuration");
+
+    let pool = db::init_pool(&config.database);
+
+    // `server migrate` applies pending migrations and exits. Otherwise they
+    // run on startup unless database.auto_migrate is off.
+    if cli.command == Command::Migrate {
+        return apply_migrations(&pool);
+    }
+    if config.database.auto_migrate {
+        apply_migrations(&pool)?;
+    }
 
-    let server_port = env::var("SERVER_PORT").unwrap_or_else(|_| "8080".to_string());
-    log::info!("Server port: {}", server_port);
+    let bind_address = (config.server.bind_address, config.server.port);
+    tracing::info!(
+        address = %config.server.bind_address,
+        port = config.server.port,
+        "starting server"
+    );
 
-    HttpServer::new(|| {
-        let cors = Cors::default()
-            .allowed_origin("http://localhost:3000")
+    let metrics = Arc::new(Metrics::default());
+    let rooms = RoomRegistry::new(metrics.clone(), config.websocket.reconnect_grace()).start();
+    let public_routes = PublicRoutes::from_config(&config);
+    tracing::info!(?public_routes, "configured public routes");
+    let shutdown_timeout = config.server.shutdown_timeout_secs;
+    let config = web::Data::new(config);
+    let registry = rooms.clone();
+
+    let server = HttpServer::new(move || {
+        let cors = config
+            .server
+            .cors_origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
             .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
             .allowed_headers(vec![
                 actix_web::http::header::AUTHORIZATION,
                 actix_web::http::header::ACCEPT,
                 actix_web::http::header::CONTENT_TYPE,
+                REQUEST_ID_HEADER,
             ])
+            .expose_headers(vec![REQUEST_ID_HEADER])
             .supports_credentials();
 
-        log::debug!("Configuring CORS with allowed origin http://localhost:3000");
-
         App::new()
+            .app_data(web::JsonConfig::default().error_handler(errors::json_error_handler))
+            .app_data(web::PathConfig::default().error_handler(errors::path_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(errors::query_error_handler))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(rooms.clone()))
+            .app_data(config.clone())
+            .app_data(web::Data::from(metrics.clone()))
             .wrap(cors)
-            .wrap(AuthMiddleware)
+            .wrap(AuthMiddleware::new(public_routes.clone()))
+            .wrap(RequestMetrics)
+            .wrap(RequestTracing)
             .route("/", web::get().to(handlers::index))
+            .route("/healthz", web::get().to(handlers::healthz_handler))
+            .route("/readyz", web::get().to(handlers::readyz_handler))
+            .route("/metrics", web::get().to(handlers::metrics_handler))
             .route("/api", web::post().to(handlers::api_handler))
             .route("/register", web::post().to(handlers::register_handler))
             .route("/login", web::post().to(handlers::login_handler))
+            .route(
+                "/token/refresh",
+                web::post().to(handlers::refresh_token_handler),
+            )
             .service(
                 web::scope("")
                     .route("/user/{id}", web::get().to(handlers::get_user))
                     .route("/user", web::post().to(handlers::create_user_handler))
+                    .route(
+                        "/api/users/{id}/role",
+                        web::put().to(handlers::update_user_role_handler),
+                    )
+                    .route("/logout", web::post().to(handlers::logout_handler))
+                    .route("/logout/all", web::post().to(handlers::logout_all_handler))
+                    .route(
+                        "/api/meetings",
+                        web::get().to(handlers::list_meetings_handler),
+                    )
                     .route(
                         "/api/meetings",
                         web::post().to(handlers::create_meeting_handler),
@@ -55,6 +187,66 @@ async fn main() -> std::io::Result<()> {
                         "/api/meetings/{id}",
                         web::get().to(handlers::get_meeting_handler),
                     )
+                    .route(
+                        "/api/meetings/{id}",
+                        web::put().to(handlers::update_meeting_handler),
+                    )
+                    .route(
+                        "/api/meetings/{id}",
+                        web::delete().to(handlers::delete_meeting_handler),
+                    )
+                    .route(
+                        "/api/meetings/{id}/participants",
+                        web::post().to(handlers::add_participant_handler),
+                    )
+                    .route(
+                        "/api/meetings/{id}/participants/{user_id}",
+                        web::delete().to(handlers::remove_participant_handler),
+                    )
+                    .route(
+                        "/api/meetings/{id}/presence",
+                        web::get().to(handlers::get_presence_handler),
+                    )
+                    .route(
+                        "/api/meetings/{id}/read",
+                        web::put().to(handlers::mark_read_handler),
+                    )
+                    .route(
+                        "/api/meetings/{id}/reads",
+                        web::get().to(handlers::list_read_receipts_handler),
+                    )
+                    .route(
+                        "/api/meetings/{id}/invitations",
+                        web::post().to(handlers::create_invitation_handler),
+                    )
+                    .route(
+                        "/api/meetings/{id}/links",
+                        web::get().to(handlers::list_join_links_handler),
+                    )
+                    .route(
+                        "/api/meetings/{id}/links",
+                        web::post().to(handlers::create_join_link_handler),
+                    )
+                    .route(
+                        "/api/meetings/{id}/links/{link_id}",
+                        web::delete().to(handlers::revoke_join_link_handler),
+                    )
+                    .route(
+                        "/api/invitations",
+                        web::get().to(handlers::list_invitations_handler),
+                    )
+                    .route(
+                        "/api/invitations/{id}/accept",
+                        web::post().to(handlers::accept_invitation_handler),
+                    )
+                    .route(
+                        "/api/invitations/{id}/decline",
+                        web::post().to(handlers::decline_invitation_handler),
+                    )
+                    .route(
+                        "/join/{token}",
+                        web::post().to(handlers::redeem_join_link_handler),
+                    )
                     .route(
                         "/api/messages",
                         web::post().to(handlers::create_message_handler),
@@ -63,10 +255,28 @@ async fn main() -> std::io::Result<()> {
                         "/api/messages/{meeting_id}",
                         web::get().to(handlers::get_messages_handler),
                     )
+                    .route(
+                        "/api/messages/{meeting_id}/{message_id}",
+                        web::put().to(handlers::edit_message_handler),
+                    )
+                    .route(
+                        "/api/messages/{meeting_id}/{message_id}",
+                        web::delete().to(handlers::delete_message_handler),
+                    )
+                    .route(
+                        "/api/messages/{meeting_id}/{message_id}/revisions",
+                        web::get().to(handlers::list_message_revisions_handler),
+                    )
                     .route("/ws/", web::get().to(ws::websocket_handler)),
             )
     })
-    .bind(format!("0.0.0.0:{}", server_port))?
-    .run()
-    .await
+    .bind(bind_address)?
+    .shutdown_timeout(shutdown_timeout)
+    // Signals are handled by `drain_on_shutdown` so sessions are told to
+    // close before the workers wait for their connections to finish.
+    .disable_signals()
+    .run();
+
+    actix_web::rt::spawn(drain_on_shutdown(server.handle(), registry));
+    server.await
 }
@@ -0,0 +1,273 @@
+use crate::db::DbPool;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use prometheus_client::encoding::text::encode;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::Registry;
+use std::rc::Rc;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct RequestLabels {
+    pub method: String,
+    /// The route pattern, e.g. `/api/meetings/{id}`, so ids do not each get
+    /// their own series.
+    pub route: String,
+    pub status: u16,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct RouteLabels {
+    pub method: String,
+    pub route: String,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct MeetingLabels {
+    pub meeting_id: i32,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct SourceLabels {
+    /// `http` or `ws`.
+    pub source: &'static str,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ReasonLabels {
+    pub reason: &'static str,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct PoolStateLabels {
+    state: &'static str,
+}
+
+fn request_duration_histogram() -> Histogram {
+    // 5ms up to about 10s.
+    Histogram::new(exponential_buckets(0.005, 2.0, 12))
+}
+
+/// Prometheus metrics for the whole server, shared as `web::Data<Metrics>`
+/// and rendered by `GET /metrics`.
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: Family<RequestLabels, Counter>,
+    pub http_request_duration: Family<RouteLabels, Histogram, fn() -> Histogram>,
+    pub ws_connections: Gauge,
+    pub ws_sessions: Family<MeetingLabels, Gauge>,
+    pub messages: Family<SourceLabels, Counter>,
+    pub auth_failures: Family<ReasonLabels, Counter>,
+    db_pool_connections: Family<PoolStateLabels, Gauge>,
+    db_pool_max_size: Gauge,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let mut metrics = Metrics {
+            registry: Registry::with_prefix("convo"),
+            http_requests: Family::default(),
+            http_request_duration: Family::new_with_constructor(request_duration_histogram),
+            ws_connections: Gauge::default(),
+            ws_sessions: Family::default(),
+            messages: Family::default(),
+            auth_failures: Family::default(),
+            db_pool_connections: Family::default(),
+            db_pool_max_size: Gauge::default(),
+        };
+
+        metrics.registry.register(
+            "http_requests",
+            "HTTP requests by method, route and status",
+            metrics.http_requests.clone(),
+        );
+        metrics.registry.register(
+            "http_request_duration_seconds",
+            "HTTP request latency by method and route",
+            metrics.http_request_duration.clone(),
+        );
+        metrics.registry.register(
+            "ws_connections",
+            "Open WebSocket connections",
+            metrics.ws_connections.clone(),
+        );
+        metrics.registry.register(
+            "ws_sessions",
+            "WebSocket sessions joined to each meeting",
+            metrics.ws_sessions.clone(),
+        );
+        metrics.registry.register(
+            "messages",
+            "Chat messages stored, by how they were sent",
+            metrics.messages.clone(),
+        );
+        metrics.registry.register(
+            "auth_failures",
+            "Rejected logins, refreshes and access tokens",
+            metrics.auth_failures.clone(),
+        );
+        metrics.registry.register(
+            "db_pool_connections",
+            "Database connections in the pool by state",
+            metrics.db_pool_connections.clone(),
+        );
+        metrics.registry.register(
+            "db_pool_max_size",
+            "Maximum number of database connections",
+            metrics.db_pool_max_size.clone(),
+        );
+
+        metrics
+    }
+}
+
+impl Metrics {
+    pub fn auth_failure(&self, reason: &'static str) {
+        self.auth_failures
+            .get_or_create(&ReasonLabels { reason })
+            .inc();
+    }
+
+    pub fn message_stored(&self, source: &'static str) {
+        self.messages.get_or_create(&SourceLabels { source }).inc();
+    }
+
+    /// Sets the number of sessions in a meeting room, dropping the series
+    /// once the room is empty.
+    pub fn set_meeting_sessions(&self, meeting_id: i32, sessions: usize) {
+        let labels = MeetingLabels { meeting_id };
+        if sessions == 0 {
+            self.ws_sessions.remove(&labels);
+        } else {
+            self.ws_sessions.get_or_create(&labels).set(sessions as i64);
+        }
+    }
+
+    /// Renders every metric in the Prometheus text format, sampling the
+    /// pool state first.
+    pub fn render(&self, pool: &DbPool) -> Result<String, std::fmt::Error> {
+        let state = pool.state();
+        let idle = i64::from(state.idle_connections);
+        self.db_pool_connections
+            .get_or_create(&PoolStateLabels { state: "idle" })
+            .set(idle);
+        self.db_pool_connections
+            .get_or_create(&PoolStateLabels { state: "in_use" })
+            .set(i64::from(state.connections) - idle);
+        self.db_pool_max_size.set(i64::from(pool.max_size()));
+
+        let mut body = String::new();
+        encode(&mut body, &self.registry)?;
+        Ok(body)
+    }
+}
+
+/// Counts and times every request by route pattern. Wrapped outermost so
+/// requests rejected by `AuthMiddleware` are counted too.
+pub struct RequestMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestMetricsMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let svc = Rc::clone(&self.service);
+        let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+        let route = RouteLabels {
+            method: req.method().to_string(),
+            route: req
+                .match_pattern()
+                .unwrap_or_else(|| "unmatched".to_string()),
+        };
+
+        Box::pin(async move {
+            let started = Instant::now();
+            let res = svc.call(req).await;
+            if let Some(metrics) = metrics {
+                let status = match &res {
+                    Ok(res) => res.status(),
+                    Err(e) => e.as_response_error().status_code(),
+                };
+                metrics
+                    .http_request_duration
+                    .get_or_create(&route)
+                    .observe(started.elapsed().as_secs_f64());
+                metrics
+                    .http_requests
+                    .get_or_create(&RequestLabels {
+                        method: route.method,
+                        route: route.route,
+                        status: status.as_u16(),
+                    })
+                    .inc();
+            }
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::pg::PgConnection;
+    use diesel::r2d2::{ConnectionManager, Pool};
+
+    #[test]
+    fn renders_counters_and_drops_empty_rooms() {
+        let pool: DbPool = Pool::builder()
+            .build_unchecked(ConnectionManager::<PgConnection>::new("postgres://unused"));
+        let metrics = Metrics::default();
+        metrics.auth_failure("missing_token");
+        metrics.message_stored("ws");
+        metrics.set_meeting_sessions(7, 2);
+        metrics.set_meeting_sessions(8, 1);
+        metrics.set_meeting_sessions(8, 0);
+
+        let body = metrics.render(&pool).unwrap();
+        assert!(body.contains("convo_auth_failures_total{reason=\"missing_token\"} 1"));
+        assert!(body.contains("convo_messages_total{source=\"ws\"} 1"));
+        assert!(body.contains("convo_ws_sessions{meeting_id=\"7\"} 2"));
+        assert!(!body.contains("meeting_id=\"8\""));
+        assert!(body.contains("convo_db_pool_max_size 10"));
+    }
+}
@@ -1,18 +1,144 @@
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::errors::ApiError;
+use crate::metrics::Metrics;
 use crate::models::Claims;
 use crate::services::decode_jwt;
+use actix_web::body::EitherBody;
 use actix_web::dev::{Service, ServiceResponse, Transform};
-use actix_web::{dev::ServiceRequest, Error, HttpMessage};
-use futures::future::{ok, LocalBoxFuture, Ready};
+use actix_web::web;
+use actix_web::{dev::Payload, dev::ServiceRequest, Error, FromRequest, HttpMessage, HttpRequest};
+use futures::future::{ok, ready, LocalBoxFuture, Ready};
+use serde::Deserialize;
+use std::collections::HashSet;
 use std::rc::Rc;
 
-pub struct AuthMiddleware;
+/// Paths that are served without a token. Entries ending in `*` match
+/// every path with that prefix.
+#[derive(Debug, Clone)]
+pub struct PublicRoutes {
+    exact: HashSet<String>,
+    prefixes: Vec<String>,
+}
+
+impl PublicRoutes {
+    pub fn new<I, S>(routes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut exact = HashSet::new();
+        let mut prefixes = Vec::new();
+        for route in routes {
+            let route = route.into();
+            match route.strip_suffix('*') {
+                Some(prefix) => prefixes.push(prefix.to_string()),
+                None => {
+                    exact.insert(route);
+                }
+            }
+        }
+        PublicRoutes { exact, prefixes }
+    }
+
+    /// The configured routes, or the defaults when none are configured.
+    pub fn from_config(config: &Config) -> Self {
+        match &config.server.public_routes {
+            Some(routes) => PublicRoutes::new(routes.iter().cloned()),
+            None => PublicRoutes::default(),
+        }
+    }
+
+    pub fn is_public(&self, path: &str) -> bool {
+        self.exact.contains(path) || self.prefixes.iter().any(|prefix| path.starts_with(prefix))
+    }
+}
+
+impl Default for PublicRoutes {
+    fn default() -> Self {
+        PublicRoutes::new([
+            "/",
+            "/register",
+            "/login",
+            "/token/refresh",
+            "/healthz",
+            "/readyz",
+            "/metrics",
+        ])
+    }
+}
+
+/// Requires a valid access token on every route except the public ones.
+pub struct AuthMiddleware {
+    public_routes: Rc<PublicRoutes>,
+}
+
+impl AuthMiddleware {
+    pub fn new(public_routes: PublicRoutes) -> Self {
+        AuthMiddleware {
+            public_routes: Rc::new(public_routes),
+        }
+    }
+}
+
+pub(crate) fn request_claims(req: &HttpRequest) -> Result<Claims, ApiError> {
+    req.extensions()
+        .get::<Claims>()
+        .cloned()
+        .ok_or_else(|| ApiError::Unauthorized("Authorization token required".to_string()))
+}
+
+/// Handlers take `Claims` as an argument to get the identity
+/// `AuthMiddleware` verified for the request.
+impl FromRequest for Claims {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(request_claims(req))
+    }
+}
+
+impl Claims {
+    pub fn user_id(&self) -> Result<i32, ApiError> {
+        self.sub
+            .parse()
+            .map_err(|_| ApiError::Unauthorized("Invalid token subject".to_string()))
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: String,
+}
+
+/// Reads the bearer token from the `Authorization` header. Browsers cannot
+/// set headers on a WebSocket handshake, so `/ws/` also accepts it as a
+/// `token` query parameter.
+fn request_token(req: &ServiceRequest) -> Option<String> {
+    if let Some(auth_header) = req.headers().get("Authorization") {
+        if let Ok(auth_str) = auth_header.to_str() {
+            if let Some(token) = auth_str.strip_prefix("Bearer ") {
+                return Some(token.to_string());
+            }
+        }
+    }
+
+    if req.path() == "/ws/" {
+        return web::Query::<TokenQuery>::from_query(req.query_string())
+            .ok()
+            .map(|query| query.into_inner().token);
+    }
+
+    None
+}
 
 impl<S, B> Transform<S, ServiceRequest> for AuthMiddleware
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Transform = AuthMiddlewareMiddleware<S>;
     type InitError = ();
@@ -21,12 +147,14 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ok(AuthMiddlewareMiddleware {
             service: Rc::new(service),
+            public_routes: Rc::clone(&self.public_routes),
         })
     }
 }
 
 pub struct AuthMiddlewareMiddleware<S> {
     service: Rc<S>,
+    public_routes: Rc<PublicRoutes>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthMiddlewareMiddleware<S>
@@ -34,7 +162,7 @@ where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
 
@@ -48,33 +176,169 @@ where
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let svc = Rc::clone(&self.service);
 
-        let path = req.path();
-        if path == "/register" || path == "/login" {
-            return Box::pin(async move { svc.call(req).await });
+        if self.public_routes.is_public(req.path()) {
+            return Box::pin(async move { Ok(svc.call(req).await?.map_into_left_body()) });
         }
 
         Box::pin(async move {
-            let headers = req.headers();
-            if let Some(auth_header) = headers.get("Authorization") {
-                if let Ok(auth_str) = auth_header.to_str() {
-                    if auth_str.starts_with("Bearer ") {
-                        let token = &auth_str[7..];
-                        match decode_jwt(token) {
-                            Ok(claims) => {
-                                req.extensions_mut().insert::<Claims>(claims);
-                                return svc.call(req).await;
-                            }
-                            Err(_) => {
-                                return Err(actix_web::error::ErrorUnauthorized("Invalid token"));
-                            }
-                        }
-                    }
+            // Rejections are answered here rather than returned as errors so
+            // that outer middleware, such as `RequestTracing`, sees them as
+            // responses.
+            match authenticate(&req).await {
+                Ok(claims) => {
+                    req.extensions_mut().insert::<Claims>(claims);
+                    Ok(svc.call(req).await?.map_into_left_body())
                 }
+                Err(e) => Ok(req.error_response(e).map_into_right_body()),
             }
+        })
+    }
+}
 
-            Err(actix_web::error::ErrorUnauthorized(
-                "Authorization token required",
-            ))
+async fn authenticate(req: &ServiceRequest) -> Result<Claims, ApiError> {
+    let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+    let Some(token) = request_token(req) else {
+        if let Some(metrics) = metrics {
+            metrics.auth_failure("missing_token");
+        }
+        return Err(ApiError::Unauthorized(
+            "Authorization token required".to_string(),
+        ));
+    };
+
+    let pool = req
+        .app_data::<web::Data<DbPool>>()
+        .cloned()
+        .ok_or_else(|| ApiError::Internal("database pool not configured".to_string()))?;
+    let config = req
+        .app_data::<web::Data<Config>>()
+        .cloned()
+        .ok_or_else(|| ApiError::Internal("server config not registered".to_string()))?;
+    decode_jwt(&pool, &config.auth, &token)
+        .await
+        .inspect_err(|e| {
+            if let (Some(metrics), ApiError::Unauthorized(_)) = (metrics, e) {
+                metrics.auth_failure("invalid_token");
+            }
         })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations;
+    use crate::models::{NewUser, UserRole};
+    use crate::repository::Repository;
+    use crate::services::generate_jwt;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App, HttpResponse};
+    use diesel::pg::PgConnection;
+    use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool};
+    use diesel::Connection;
+    use std::env;
+
+    /// Keeps every pooled connection inside a transaction that is never
+    /// committed.
+    #[derive(Debug)]
+    struct TestTransaction;
+
+    impl CustomizeConnection<PgConnection, diesel::r2d2::Error> for TestTransaction {
+        fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), diesel::r2d2::Error> {
+            conn.begin_test_transaction()
+                .map_err(diesel::r2d2::Error::QueryError)
+        }
+    }
+
+    /// A single-connection pool on `TEST_DATABASE_URL`, so rows a test
+    /// inserts are visible to the middleware and rolled back afterwards.
+    fn test_pool() -> DbPool {
+        let database_url = env::var("TEST_DATABASE_URL")
+            .expect("TEST_DATABASE_URL must name a throwaway database to run database tests");
+        let mut conn = PgConnection::establish(&database_url)
+            .expect("TEST_DATABASE_URL should point at a reachable database");
+        run_migrations(&mut conn).expect("failed to migrate the test database");
+        Pool::builder()
+            .max_size(1)
+            .connection_customizer(Box::new(TestTransaction))
+            .build(ConnectionManager::<PgConnection>::new(database_url))
+            .expect("TEST_DATABASE_URL should point at a reachable database")
+    }
+
+    /// A pool that is never connected, for requests rejected before any query.
+    fn unused_pool() -> DbPool {
+        Pool::builder().build_unchecked(ConnectionManager::<PgConnection>::new(
+            "postgres://localhost:1/unreachable",
+        ))
+    }
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.auth.jwt_secret = "middleware-test-secret".to_string();
+        config
+    }
+
+    async fn whoami(claims: Claims) -> HttpResponse {
+        HttpResponse::Ok().body(claims.sub)
+    }
+
+    /// Sends a GET through `AuthMiddleware` and reads the response, which is
+    /// the middleware's own for rejected requests.
+    async fn get(pool: DbPool, uri: &str) -> (StatusCode, String) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(config()))
+                .wrap(AuthMiddleware::new(PublicRoutes::default()))
+                .route("/ws/", web::get().to(whoami))
+                .route("/api/me", web::get().to(whoami)),
+        )
+        .await;
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let res = test::call_service(&app, req).await;
+        let status = res.status();
+        let body = test::read_body(res).await;
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    #[actix_web::test]
+    async fn websocket_handshake_without_a_token_is_rejected() {
+        let (status, _) = get(unused_pool(), "/ws/").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn websocket_query_token_must_be_valid() {
+        let (status, _) = get(unused_pool(), "/ws/?token=not-a-jwt").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn query_token_authenticates_only_the_websocket_route() {
+        let pool = test_pool();
+        let (user_id, session_id) = {
+            let mut conn = pool.get().expect("pool connection");
+            let user = conn
+                .create_user(NewUser {
+                    name: "ws-token".to_string(),
+                    email: "ws-token@example.com".to_string(),
+                    password: "hashed".to_string(),
+                })
+                .expect("create user");
+            let expires_at = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+            let session = conn
+                .create_session(user.id, "ws-token-refresh".to_string(), expires_at)
+                .expect("create session");
+            (user.id, session.id)
+        };
+        let token = generate_jwt(&config().auth, user_id, session_id, UserRole::Member)
+            .expect("sign token");
+
+        let (status, body) = get(pool.clone(), &format!("/ws/?token={}", token)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, user_id.to_string());
+
+        let (status, _) = get(pool, &format!("/api/me?token={}", token)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
     }
 }
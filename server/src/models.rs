@@ -1,24 +1,159 @@
-use crate::schema::{meetings, messages, users};
+use crate::schema::{
+    auth_sessions, meeting_invitations, meeting_join_links, meeting_participants, meetings,
+    message_reads, message_revisions, messages, refresh_tokens, users,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use chrono::NaiveDateTime;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
 use diesel::prelude::*;
-use serde::{Deserialize, Serialize};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::io::Write;
+use std::str::FromStr;
 
+/// Stores an enum with `as_str` and `FromStr` in a `VARCHAR` column.
+macro_rules! text_enum_sql {
+    ($enum:ty) => {
+        impl ToSql<Text, Pg> for $enum {
+            fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+                out.write_all(self.as_str().as_bytes())?;
+                Ok(serialize::IsNull::No)
+            }
+        }
 
-#[derive(Debug, Serialize, Deserialize)]
+        impl FromSql<Text, Pg> for $enum {
+            fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+                Ok(std::str::from_utf8(bytes.as_bytes())?.parse()?)
+            }
+        }
+    };
+}
+
+/// Implements `Debug` showing the first list of fields and `[redacted]` for
+/// the second, so a value that ends up in a log line cannot leak secrets.
+macro_rules! redacted_debug {
+    ($type:ident, [$($field:ident),*], [$($secret:ident),*]) => {
+        impl std::fmt::Debug for $type {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct(stringify!($type))
+                    $(.field(stringify!($field), &self.$field))*
+                    $(.field(stringify!($secret), &crate::telemetry::REDACTED))*
+                    .finish()
+            }
+        }
+    };
+}
+
+/// Access token payload. `sid` is the login session the token belongs to,
+/// so revoking the session invalidates the token before it expires. `role`
+/// is the user's role when the token was issued; a changed role applies
+/// from the next refresh.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
-    pub sub: String, 
-    pub exp: usize,  
+    pub sub: String,
+    pub sid: i32,
+    pub role: UserRole,
+    pub exp: usize,
 }
 
-#[derive(Debug, Queryable, Serialize, Deserialize)]
+/// Server-wide role of an account, independent of any meeting.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum UserRole {
+    Guest,
+    Member,
+    Admin,
+}
+
+impl UserRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::Guest => "guest",
+            UserRole::Member => "member",
+            UserRole::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for UserRole {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "guest" => Ok(UserRole::Guest),
+            "member" => Ok(UserRole::Member),
+            "admin" => Ok(UserRole::Admin),
+            other => Err(format!("Unknown user role: {}", other)),
+        }
+    }
+}
+
+text_enum_sql!(UserRole);
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUserRoleRequest {
+    pub role: UserRole,
+}
+
+/// Payload of a signed join link. It has no `sub`, so a join token can
+/// never be mistaken for a session token or the other way round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JoinLinkClaims {
+    pub link_id: i32,
+    pub meeting_id: i32,
+    pub exp: usize,
+}
+
+#[derive(Queryable, Serialize, Deserialize)]
 pub struct User {
     pub id: i32,
     pub name: String,
     pub email: String,
+    /// The bcrypt hash. Never serialized, so it cannot reach a response.
+    #[serde(skip_serializing)]
     pub password: String,
+    pub role: UserRole,
 }
 
-#[derive(Debug, Insertable, Serialize, Deserialize, Clone)]
+redacted_debug!(User, [id, name, role], [email, password]);
+
+/// What other users may see of an account: never its email or password
+/// hash.
+#[derive(Debug, Clone, Serialize)]
+pub struct PublicUser {
+    pub id: i32,
+    pub name: String,
+    pub role: UserRole,
+}
+
+impl From<User> for PublicUser {
+    fn from(user: User) -> Self {
+        PublicUser {
+            id: user.id,
+            name: user.name,
+            role: user.role,
+        }
+    }
+}
+
+#[derive(Insertable, Clone)]
 #[diesel(table_name = users)]
 pub struct NewUser {
     pub name: String,
@@ -26,12 +161,105 @@ pub struct NewUser {
     pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
+redacted_debug!(NewUser, [name], [email, password]);
+
+/// Body of `POST /register` and `POST /user`. The password is hashed before
+/// it becomes a `NewUser`.
+#[derive(Deserialize)]
+pub struct RegisterRequest {
+    pub name: String,
+    pub email: String,
+    pub password: String,
+}
+
+redacted_debug!(RegisterRequest, [name], [email, password]);
+
+#[derive(Deserialize)]
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
 }
 
+redacted_debug!(LoginRequest, [], [email, password]);
+
+/// One login on one device. Refresh tokens rotate within a session; logging
+/// out revokes it.
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct AuthSession {
+    pub id: i32,
+    pub user_id: i32,
+    pub created_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = auth_sessions)]
+pub struct NewAuthSession {
+    pub user_id: i32,
+}
+
+#[derive(Clone, Queryable)]
+pub struct RefreshToken {
+    pub id: i32,
+    pub session_id: i32,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub rotated_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+redacted_debug!(
+    RefreshToken,
+    [id, session_id, expires_at, rotated_at, created_at],
+    [token_hash]
+);
+
+#[derive(Insertable)]
+#[diesel(table_name = refresh_tokens)]
+pub struct NewRefreshToken {
+    pub session_id: i32,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+}
+
+redacted_debug!(NewRefreshToken, [session_id, expires_at], [token_hash]);
+
+/// What presenting a refresh token led to.
+#[derive(Debug)]
+pub enum RefreshOutcome {
+    /// The token was valid and has been replaced by a new one.
+    Rotated(AuthSession),
+    /// The token had already been rotated, so it was stolen or replayed; the
+    /// whole session has been revoked.
+    Reused(AuthSession),
+    Expired,
+    Revoked,
+    Unknown,
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+redacted_debug!(RefreshRequest, [], [refresh_token]);
+
+/// Returned by `POST /login` and `POST /token/refresh`.
+#[derive(Serialize)]
+pub struct TokenResponse {
+    pub token: String,
+    pub token_type: &'static str,
+    /// Lifetime of `token` in seconds.
+    pub expires_in: i64,
+    pub refresh_token: String,
+}
+
+redacted_debug!(
+    TokenResponse,
+    [token_type, expires_in],
+    [token, refresh_token]
+);
+
 #[derive(Debug, Queryable, Serialize, Deserialize)]
 pub struct Meeting {
     pub id: i32,
@@ -42,7 +270,7 @@ pub struct Meeting {
     pub creator_id: i32,
 }
 
-#[derive(Debug, Insertable, Serialize, Deserialize)]
+#[derive(Debug, Insertable)]
 #[diesel(table_name = meetings)]
 pub struct NewMeeting {
     pub title: String,
@@ -54,16 +282,352 @@ pub struct NewMeeting {
     pub creator_id: i32,
 }
 
-#[derive(Debug, Queryable, Serialize, Deserialize)]
+/// Body of `POST /api/meetings`. The creator is the authenticated user;
+/// `creator_id` is only accepted for compatibility and must match it.
+#[derive(Debug, Deserialize)]
+pub struct CreateMeetingRequest {
+    pub title: String,
+    pub description: Option<String>,
+    pub start_time: NaiveDateTime,
+    pub end_time: NaiveDateTime,
+    pub creator_id: Option<i32>,
+}
+
+/// Lets a PATCH-style field tell "absent" (`None`) apart from an explicit
+/// `null` (`Some(None)`).
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Partial update of a meeting; only the fields present in the body change.
+#[derive(Debug, Default, AsChangeset, Deserialize)]
+#[diesel(table_name = meetings)]
+pub struct UpdateMeeting {
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub description: Option<Option<String>>,
+    pub start_time: Option<NaiveDateTime>,
+    pub end_time: Option<NaiveDateTime>,
+}
+
+impl UpdateMeeting {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.description.is_none()
+            && self.start_time.is_none()
+            && self.end_time.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MeetingTimeframe {
+    Upcoming,
+    Past,
+}
+
+/// Query parameters accepted by `GET /api/meetings`. `from` and `to` bound
+/// the meeting's start time.
+#[derive(Debug, Default, Deserialize)]
+pub struct MeetingFilter {
+    pub when: Option<MeetingTimeframe>,
+    pub creator_id: Option<i32>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+/// What a participant may do in a meeting. The creator is always the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticipantRole {
+    Host,
+    CoHost,
+    Attendee,
+    Viewer,
+}
+
+impl ParticipantRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParticipantRole::Host => "host",
+            ParticipantRole::CoHost => "co_host",
+            ParticipantRole::Attendee => "attendee",
+            ParticipantRole::Viewer => "viewer",
+        }
+    }
+
+    /// Hosts and co-hosts may add and remove other participants.
+    pub fn can_manage(&self) -> bool {
+        matches!(self, ParticipantRole::Host | ParticipantRole::CoHost)
+    }
+
+    /// Viewers can follow a meeting but not post to it.
+    pub fn can_post(&self) -> bool {
+        !matches!(self, ParticipantRole::Viewer)
+    }
+}
+
+impl FromStr for ParticipantRole {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "host" => Ok(ParticipantRole::Host),
+            "co_host" => Ok(ParticipantRole::CoHost),
+            "attendee" => Ok(ParticipantRole::Attendee),
+            "viewer" => Ok(ParticipantRole::Viewer),
+            other => Err(format!("Unknown participant role: {}", other)),
+        }
+    }
+}
+
+text_enum_sql!(ParticipantRole);
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct Participant {
+    pub meeting_id: i32,
+    pub user_id: i32,
+    pub role: ParticipantRole,
+    pub joined_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = meeting_participants)]
+pub struct NewParticipant {
+    pub meeting_id: i32,
+    pub user_id: i32,
+    pub role: ParticipantRole,
+}
+
+/// Body of `POST /api/meetings/{id}/participants`. The role defaults to
+/// attendee.
+#[derive(Debug, Deserialize)]
+pub struct AddParticipantRequest {
+    pub user_id: i32,
+    pub role: Option<ParticipantRole>,
+}
+
+/// A participant as listed alongside its meeting.
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct ParticipantSummary {
+    pub id: i32,
+    pub name: String,
+    pub role: ParticipantRole,
+}
+
+/// A meeting together with everyone who belongs to it, as returned by
+/// `GET /api/meetings/{id}`.
+#[derive(Debug, Serialize)]
+pub struct MeetingDetails {
+    #[serde(flatten)]
+    pub meeting: Meeting,
+    pub participants: Vec<ParticipantSummary>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum InvitationStatus {
+    Pending,
+    Accepted,
+    Declined,
+}
+
+impl InvitationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvitationStatus::Pending => "pending",
+            InvitationStatus::Accepted => "accepted",
+            InvitationStatus::Declined => "declined",
+        }
+    }
+}
+
+impl FromStr for InvitationStatus {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pending" => Ok(InvitationStatus::Pending),
+            "accepted" => Ok(InvitationStatus::Accepted),
+            "declined" => Ok(InvitationStatus::Declined),
+            other => Err(format!("Unknown invitation status: {}", other)),
+        }
+    }
+}
+
+text_enum_sql!(InvitationStatus);
+
+/// An invitation addressed to a registered user or to an email address.
+#[derive(Clone, Queryable, Serialize)]
+pub struct Invitation {
+    pub id: i32,
+    pub meeting_id: i32,
+    pub inviter_id: i32,
+    pub invitee_user_id: Option<i32>,
+    pub invitee_email: Option<String>,
+    pub role: ParticipantRole,
+    pub status: InvitationStatus,
+    pub created_at: NaiveDateTime,
+}
+
+redacted_debug!(
+    Invitation,
+    [
+        id,
+        meeting_id,
+        inviter_id,
+        invitee_user_id,
+        role,
+        status,
+        created_at
+    ],
+    [invitee_email]
+);
+
+impl Invitation {
+    pub fn is_addressed_to(&self, user: &User) -> bool {
+        self.invitee_user_id == Some(user.id)
+            || self
+                .invitee_email
+                .as_deref()
+                .is_some_and(|email| email.eq_ignore_ascii_case(&user.email))
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = meeting_invitations)]
+pub struct NewInvitation {
+    pub meeting_id: i32,
+    pub inviter_id: i32,
+    pub invitee_user_id: Option<i32>,
+    pub invitee_email: Option<String>,
+    pub role: ParticipantRole,
+}
+
+redacted_debug!(
+    NewInvitation,
+    [meeting_id, inviter_id, invitee_user_id, role],
+    [invitee_email]
+);
+
+/// Body of `POST /api/meetings/{id}/invitations`. Exactly one of `user_id`
+/// and `email` must be given; the role defaults to attendee.
+#[derive(Deserialize)]
+pub struct CreateInvitationRequest {
+    pub user_id: Option<i32>,
+    pub email: Option<String>,
+    pub role: Option<ParticipantRole>,
+}
+
+redacted_debug!(CreateInvitationRequest, [user_id, role], [email]);
+
+#[derive(Clone, Queryable, Serialize)]
+pub struct JoinLink {
+    pub id: i32,
+    pub meeting_id: i32,
+    pub created_by: i32,
+    pub role: ParticipantRole,
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    #[serde(skip)]
+    pub passcode_hash: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+redacted_debug!(
+    JoinLink,
+    [id, meeting_id, created_by, role, max_uses, uses, expires_at, revoked_at, created_at],
+    [passcode_hash]
+);
+
+#[derive(Insertable)]
+#[diesel(table_name = meeting_join_links)]
+pub struct NewJoinLink {
+    pub meeting_id: i32,
+    pub created_by: i32,
+    pub role: ParticipantRole,
+    pub max_uses: Option<i32>,
+    pub passcode_hash: Option<String>,
+    pub expires_at: NaiveDateTime,
+}
+
+redacted_debug!(
+    NewJoinLink,
+    [meeting_id, created_by, role, max_uses, expires_at],
+    [passcode_hash]
+);
+
+/// Body of `POST /api/meetings/{id}/links`. Links expire after 24 hours
+/// unless `expires_in_hours` says otherwise.
+#[derive(Default, Deserialize)]
+pub struct CreateJoinLinkRequest {
+    pub role: Option<ParticipantRole>,
+    pub max_uses: Option<i32>,
+    pub passcode: Option<String>,
+    pub expires_in_hours: Option<i64>,
+}
+
+redacted_debug!(
+    CreateJoinLinkRequest,
+    [role, max_uses, expires_in_hours],
+    [passcode]
+);
+
+/// A join link together with the signed token that redeems it.
+#[derive(Serialize)]
+pub struct JoinLinkResponse {
+    #[serde(flatten)]
+    pub link: JoinLink,
+    pub has_passcode: bool,
+    pub token: String,
+    pub url: String,
+}
+
+redacted_debug!(JoinLinkResponse, [link, has_passcode], [token, url]);
+
+#[derive(Default, Deserialize)]
+pub struct RedeemJoinLinkRequest {
+    pub passcode: Option<String>,
+}
+
+redacted_debug!(RedeemJoinLinkRequest, [], [passcode]);
+
+/// A chat message. A deleted message is kept as a tombstone with empty
+/// content so pages and read markers stay intact.
+#[derive(Debug, Clone, Queryable, Serialize, Deserialize)]
 pub struct Message {
     pub id: i32,
     pub content: String,
     pub sender_id: i32,
     pub meeting_id: i32,
     pub timestamp: NaiveDateTime,
+    pub edited_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
-#[derive(Debug, Insertable, Serialize, Deserialize)]
+impl From<Message> for protocol::ChatMessage {
+    fn from(message: Message) -> Self {
+        protocol::ChatMessage {
+            id: message.id,
+            content: message.content,
+            sender_id: message.sender_id,
+            meeting_id: message.meeting_id,
+            timestamp: message.timestamp,
+            edited_at: message.edited_at,
+            deleted_at: message.deleted_at,
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
 #[diesel(table_name = messages)]
 pub struct NewMessage {
     pub content: String,
@@ -71,4 +635,196 @@ pub struct NewMessage {
     pub meeting_id: i32,
     #[diesel(sql_type = Timestamp)]
     pub timestamp: NaiveDateTime,
-}
\ No newline at end of file
+}
+
+/// Body of `POST /api/messages`. The sender is the authenticated user and
+/// the timestamp is assigned by the server; `sender_id` is only accepted for
+/// compatibility and must match the caller.
+#[derive(Debug, Deserialize)]
+pub struct CreateMessageRequest {
+    pub content: String,
+    pub meeting_id: i32,
+    pub sender_id: Option<i32>,
+}
+
+/// Body of `PUT /api/messages/{meeting_id}/{message_id}`.
+#[derive(Debug, Deserialize)]
+pub struct EditMessageRequest {
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum RevisionAction {
+    Edit,
+    Delete,
+}
+
+impl RevisionAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RevisionAction::Edit => "edit",
+            RevisionAction::Delete => "delete",
+        }
+    }
+}
+
+impl FromStr for RevisionAction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "edit" => Ok(RevisionAction::Edit),
+            "delete" => Ok(RevisionAction::Delete),
+            other => Err(format!("Unknown revision action: {}", other)),
+        }
+    }
+}
+
+text_enum_sql!(RevisionAction);
+
+/// The content a message had before it was edited or deleted, and who
+/// changed it.
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct MessageRevision {
+    pub id: i32,
+    pub message_id: i32,
+    pub content: String,
+    pub action: RevisionAction,
+    pub changed_by: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = message_revisions)]
+pub struct NewMessageRevision {
+    pub message_id: i32,
+    pub content: String,
+    pub action: RevisionAction,
+    pub changed_by: i32,
+    pub created_at: NaiveDateTime,
+}
+
+/// The last message a participant has read in a meeting.
+#[derive(Debug, Clone, Queryable, Insertable, Serialize)]
+#[diesel(table_name = message_reads)]
+pub struct ReadReceipt {
+    pub meeting_id: i32,
+    pub user_id: i32,
+    pub last_read_message_id: i32,
+    /// The timestamp of that message.
+    #[serde(skip)]
+    pub last_read_timestamp: NaiveDateTime,
+    pub read_at: NaiveDateTime,
+}
+
+/// Body of `PUT /api/meetings/{id}/read`.
+#[derive(Debug, Deserialize)]
+pub struct MarkReadRequest {
+    pub message_id: i32,
+}
+
+/// A meeting as listed by `GET /api/meetings`. `unread_count` is only set
+/// for meetings the caller participates in.
+#[derive(Debug, Serialize)]
+pub struct MeetingSummary {
+    #[serde(flatten)]
+    pub meeting: Meeting,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unread_count: Option<i64>,
+}
+
+/// A position in a meeting's messages, which are ordered by
+/// `(timestamp, id)`. Handed to clients as an opaque token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageCursor {
+    pub timestamp: NaiveDateTime,
+    pub id: i32,
+}
+
+impl MessageCursor {
+    pub fn of(message: &Message) -> Self {
+        MessageCursor {
+            timestamp: message.timestamp,
+            id: message.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let raw = format!(
+            "{}:{}",
+            self.timestamp.and_utc().timestamp_micros(),
+            self.id
+        );
+        URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(token).ok()?).ok()?;
+        let (micros, id) = raw.split_once(':')?;
+        Some(MessageCursor {
+            timestamp: chrono::DateTime::from_timestamp_micros(micros.parse().ok()?)?.naive_utc(),
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+/// Which way a page of messages extends from its cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageDirection {
+    /// Older messages, ending right before the cursor or at the newest
+    /// message.
+    Before,
+    /// Newer messages, starting right after the cursor.
+    After,
+}
+
+/// A validated page request; see `services::message_page_request`.
+#[derive(Debug, Clone, Copy)]
+pub struct MessagePageRequest {
+    pub direction: PageDirection,
+    pub cursor: Option<MessageCursor>,
+    pub limit: i64,
+}
+
+/// Query parameters accepted by `GET /api/messages/{meeting_id}`.
+#[derive(Debug, Default, Deserialize)]
+pub struct MessagePageQuery {
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Messages in ascending `(timestamp, id)` order. `next_cursor` continues
+/// in the same direction as the request and is absent on the last page.
+#[derive(Debug, Serialize)]
+pub struct MessagePage {
+    pub messages: Vec<Message>,
+    pub next_cursor: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn users_serialize_without_their_password_hash() {
+        let user = User {
+            id: 3,
+            name: "carol".to_string(),
+            email: "carol@example.com".to_string(),
+            password: "$2b$12$hash".to_string(),
+            role: UserRole::Member,
+        };
+        assert_eq!(
+            serde_json::to_value(&user).unwrap(),
+            json!({ "id": 3, "name": "carol", "email": "carol@example.com", "role": "member" })
+        );
+        assert_eq!(
+            serde_json::to_value(PublicUser::from(user)).unwrap(),
+            json!({ "id": 3, "name": "carol", "role": "member" })
+        );
+    }
+}
@@ -0,0 +1,691 @@
+use crate::models::{
+    AuthSession, Invitation, InvitationStatus, JoinLink, Meeting, MeetingFilter, MeetingTimeframe,
+    Message, MessageCursor, MessagePage, MessagePageRequest, MessageRevision, NewAuthSession,
+    NewInvitation, NewJoinLink, NewMeeting, NewMessage, NewMessageRevision, NewParticipant,
+    NewRefreshToken, NewUser, PageDirection, Participant, ParticipantRole, ParticipantSummary,
+    ReadReceipt, RefreshOutcome, RefreshToken, RevisionAction, UpdateMeeting, User, UserRole,
+};
+use crate::schema::{
+    auth_sessions, meeting_invitations, meeting_join_links, meeting_participants, meetings,
+    message_reads, message_revisions, messages, refresh_tokens, users,
+};
+use chrono::NaiveDateTime;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{Nullable, Text};
+use std::collections::HashMap;
+
+define_sql_function!(fn lower(x: Nullable<Text>) -> Nullable<Text>);
+
+/// Every query the server runs. Handlers reach the database only through
+/// this trait, on a connection checked out by `db::run`.
+pub trait Repository {
+    /// Round-trips a trivial query; used by the readiness check.
+    fn ping(&mut self) -> QueryResult<()>;
+
+    fn get_user_by_id(&mut self, user_id: i32) -> QueryResult<User>;
+    fn get_user_by_email(&mut self, user_email: &str) -> QueryResult<User>;
+    fn create_user(&mut self, new_user: NewUser) -> QueryResult<User>;
+    fn set_user_role(&mut self, user_id: i32, role: UserRole) -> QueryResult<User>;
+
+    /// Opens a login session together with its first refresh token.
+    fn create_session(
+        &mut self,
+        user_id: i32,
+        token_hash: String,
+        expires_at: NaiveDateTime,
+    ) -> QueryResult<AuthSession>;
+    fn get_session(&mut self, session_id: i32) -> QueryResult<AuthSession>;
+    /// Swaps the refresh token with hash `token_hash` for one with
+    /// `new_token_hash`. Presenting a token that was already rotated revokes
+    /// its session.
+    fn rotate_refresh_token(
+        &mut self,
+        token_hash: &str,
+        new_token_hash: String,
+        expires_at: NaiveDateTime,
+        now: NaiveDateTime,
+    ) -> QueryResult<RefreshOutcome>;
+    fn revoke_session(&mut self, session_id: i32, now: NaiveDateTime) -> QueryResult<usize>;
+    fn revoke_user_sessions(&mut self, user_id: i32, now: NaiveDateTime) -> QueryResult<usize>;
+
+    /// Creates the meeting and adds its creator as the host.
+    fn create_meeting(&mut self, new_meeting: NewMeeting) -> QueryResult<Meeting>;
+    fn get_meeting_by_id(&mut self, meeting_id: i32) -> QueryResult<Meeting>;
+    fn list_meetings(
+        &mut self,
+        filter: &MeetingFilter,
+        now: NaiveDateTime,
+    ) -> QueryResult<Vec<Meeting>>;
+    fn update_meeting(&mut self, meeting_id: i32, changes: UpdateMeeting) -> QueryResult<Meeting>;
+    /// Deletes the meeting; its messages go with it through `ON DELETE CASCADE`.
+    fn delete_meeting(&mut self, meeting_id: i32) -> QueryResult<usize>;
+
+    fn get_participant(&mut self, meeting_id: i32, user_id: i32) -> QueryResult<Participant>;
+    fn list_participants(&mut self, meeting_id: i32) -> QueryResult<Vec<ParticipantSummary>>;
+    fn add_participant(&mut self, participant: NewParticipant) -> QueryResult<Participant>;
+    fn remove_participant(&mut self, meeting_id: i32, user_id: i32) -> QueryResult<usize>;
+
+    fn create_invitation(&mut self, invitation: NewInvitation) -> QueryResult<Invitation>;
+    fn get_invitation(&mut self, invitation_id: i32) -> QueryResult<Invitation>;
+    /// Pending invitations addressed to the user's id or, case-insensitively,
+    /// to their email.
+    fn list_pending_invitations(&mut self, user: &User) -> QueryResult<Vec<Invitation>>;
+    /// Marks a pending invitation accepted and adds the user with the
+    /// invited role, keeping their current role if they already belong to
+    /// the meeting.
+    fn accept_invitation(&mut self, invitation_id: i32, user_id: i32) -> QueryResult<Participant>;
+    fn decline_invitation(&mut self, invitation_id: i32) -> QueryResult<Invitation>;
+
+    fn create_join_link(&mut self, link: NewJoinLink) -> QueryResult<JoinLink>;
+    fn get_join_link(&mut self, link_id: i32) -> QueryResult<JoinLink>;
+    fn list_join_links(&mut self, meeting_id: i32) -> QueryResult<Vec<JoinLink>>;
+    fn revoke_join_link(&mut self, link_id: i32, now: NaiveDateTime) -> QueryResult<JoinLink>;
+    /// Consumes one use of a live link and adds the user with the link's
+    /// role. Returns `None` when the link is revoked, expired or used up.
+    fn redeem_join_link(
+        &mut self,
+        link_id: i32,
+        user_id: i32,
+        now: NaiveDateTime,
+    ) -> QueryResult<Option<Participant>>;
+
+    fn create_message(&mut self, new_message: NewMessage) -> QueryResult<Message>;
+    /// One page of a meeting's messages, see `MessagePageRequest`.
+    fn get_message_page(
+        &mut self,
+        meeting_id: i32,
+        page: &MessagePageRequest,
+    ) -> QueryResult<MessagePage>;
+    fn get_message(&mut self, meeting_id: i32, message_id: i32) -> QueryResult<Message>;
+    /// Replaces the content of a message that is not deleted, recording the
+    /// previous content as a revision.
+    fn edit_message(
+        &mut self,
+        message_id: i32,
+        editor_id: i32,
+        content: &str,
+        now: NaiveDateTime,
+    ) -> QueryResult<Message>;
+    /// Turns a message that is not deleted into a tombstone with empty
+    /// content, recording the previous content as a revision.
+    fn delete_message(
+        &mut self,
+        message_id: i32,
+        deleter_id: i32,
+        now: NaiveDateTime,
+    ) -> QueryResult<Message>;
+    fn list_message_revisions(&mut self, message_id: i32) -> QueryResult<Vec<MessageRevision>>;
+
+    /// Moves a user's read marker in a meeting forward to `message_id`,
+    /// which must be a message of that meeting. A marker that is already
+    /// further along in (timestamp, id) order is left alone. Returns the
+    /// marker as stored.
+    fn mark_read(
+        &mut self,
+        meeting_id: i32,
+        user_id: i32,
+        message_id: i32,
+        now: NaiveDateTime,
+    ) -> QueryResult<ReadReceipt>;
+    fn list_read_receipts(&mut self, meeting_id: i32) -> QueryResult<Vec<ReadReceipt>>;
+    /// Counts the messages from others that are not deleted past the user's read marker, for
+    /// each of the given meetings the user participates in.
+    fn unread_counts(
+        &mut self,
+        user_id: i32,
+        meeting_ids: &[i32],
+    ) -> QueryResult<HashMap<i32, i64>>;
+}
+
+impl Repository for PgConnection {
+    fn ping(&mut self) -> QueryResult<()> {
+        diesel::sql_query("SELECT 1").execute(self).map(|_| ())
+    }
+
+    fn get_user_by_id(&mut self, user_id: i32) -> QueryResult<User> {
+        users::table.find(user_id).first::<User>(self)
+    }
+
+    fn get_user_by_email(&mut self, user_email: &str) -> QueryResult<User> {
+        users::table
+            .filter(users::email.eq(user_email))
+            .first::<User>(self)
+    }
+
+    fn create_user(&mut self, new_user: NewUser) -> QueryResult<User> {
+        diesel::insert_into(users::table)
+            .values(&new_user)
+            .get_result::<User>(self)
+    }
+
+    fn set_user_role(&mut self, user_id: i32, role: UserRole) -> QueryResult<User> {
+        diesel::update(users::table.find(user_id))
+            .set(users::role.eq(role))
+            .get_result(self)
+    }
+
+    fn create_session(
+        &mut self,
+        user_id: i32,
+        token_hash: String,
+        expires_at: NaiveDateTime,
+    ) -> QueryResult<AuthSession> {
+        self.transaction(|conn| {
+            let session: AuthSession = diesel::insert_into(auth_sessions::table)
+                .values(&NewAuthSession { user_id })
+                .get_result(conn)?;
+            diesel::insert_into(refresh_tokens::table)
+                .values(&NewRefreshToken {
+                    session_id: session.id,
+                    token_hash,
+                    expires_at,
+                })
+                .execute(conn)?;
+            Ok(session)
+        })
+    }
+
+    fn get_session(&mut self, session_id: i32) -> QueryResult<AuthSession> {
+        auth_sessions::table
+            .find(session_id)
+            .first::<AuthSession>(self)
+    }
+
+    fn rotate_refresh_token(
+        &mut self,
+        token_hash: &str,
+        new_token_hash: String,
+        expires_at: NaiveDateTime,
+        now: NaiveDateTime,
+    ) -> QueryResult<RefreshOutcome> {
+        self.transaction(|conn| {
+            // Locking the row makes concurrent refreshes with the same token
+            // queue up, so only the first one can rotate it.
+            let token: Option<RefreshToken> = refresh_tokens::table
+                .filter(refresh_tokens::token_hash.eq(token_hash))
+                .for_update()
+                .first(conn)
+                .optional()?;
+            let Some(token) = token else {
+                return Ok(RefreshOutcome::Unknown);
+            };
+            let session = conn.get_session(token.session_id)?;
+
+            if session.revoked_at.is_some() {
+                return Ok(RefreshOutcome::Revoked);
+            }
+            if token.rotated_at.is_some() {
+                conn.revoke_session(session.id, now)?;
+                return Ok(RefreshOutcome::Reused(session));
+            }
+            if token.expires_at <= now {
+                return Ok(RefreshOutcome::Expired);
+            }
+
+            diesel::update(refresh_tokens::table.find(token.id))
+                .set(refresh_tokens::rotated_at.eq(now))
+                .execute(conn)?;
+            diesel::insert_into(refresh_tokens::table)
+                .values(&NewRefreshToken {
+                    session_id: session.id,
+                    token_hash: new_token_hash,
+                    expires_at,
+                })
+                .execute(conn)?;
+            Ok(RefreshOutcome::Rotated(session))
+        })
+    }
+
+    fn revoke_session(&mut self, session_id: i32, now: NaiveDateTime) -> QueryResult<usize> {
+        diesel::update(
+            auth_sessions::table
+                .find(session_id)
+                .filter(auth_sessions::revoked_at.is_null()),
+        )
+        .set(auth_sessions::revoked_at.eq(now))
+        .execute(self)
+    }
+
+    fn revoke_user_sessions(&mut self, user_id: i32, now: NaiveDateTime) -> QueryResult<usize> {
+        diesel::update(
+            auth_sessions::table
+                .filter(auth_sessions::user_id.eq(user_id))
+                .filter(auth_sessions::revoked_at.is_null()),
+        )
+        .set(auth_sessions::revoked_at.eq(now))
+        .execute(self)
+    }
+
+    fn create_meeting(&mut self, new_meeting: NewMeeting) -> QueryResult<Meeting> {
+        self.transaction(|conn| {
+            let meeting: Meeting = diesel::insert_into(meetings::table)
+                .values(&new_meeting)
+                .get_result(conn)?;
+            conn.add_participant(NewParticipant {
+                meeting_id: meeting.id,
+                user_id: meeting.creator_id,
+                role: ParticipantRole::Host,
+            })?;
+            Ok(meeting)
+        })
+    }
+
+    fn get_meeting_by_id(&mut self, meeting_id: i32) -> QueryResult<Meeting> {
+        meetings::table.find(meeting_id).first::<Meeting>(self)
+    }
+
+    fn list_meetings(
+        &mut self,
+        filter: &MeetingFilter,
+        now: NaiveDateTime,
+    ) -> QueryResult<Vec<Meeting>> {
+        let mut query = meetings::table.into_boxed();
+
+        match filter.when {
+            Some(MeetingTimeframe::Upcoming) => query = query.filter(meetings::end_time.ge(now)),
+            Some(MeetingTimeframe::Past) => query = query.filter(meetings::end_time.lt(now)),
+            None => (),
+        }
+        if let Some(creator_id) = filter.creator_id {
+            query = query.filter(meetings::creator_id.eq(creator_id));
+        }
+        if let Some(from) = filter.from {
+            query = query.filter(meetings::start_time.ge(from));
+        }
+        if let Some(to) = filter.to {
+            query = query.filter(meetings::start_time.le(to));
+        }
+
+        query
+            .order((meetings::start_time.asc(), meetings::id.asc()))
+            .load::<Meeting>(self)
+    }
+
+    fn update_meeting(&mut self, meeting_id: i32, changes: UpdateMeeting) -> QueryResult<Meeting> {
+        diesel::update(meetings::table.find(meeting_id))
+            .set(&changes)
+            .get_result(self)
+    }
+
+    fn delete_meeting(&mut self, meeting_id: i32) -> QueryResult<usize> {
+        diesel::delete(meetings::table.find(meeting_id)).execute(self)
+    }
+
+    fn get_participant(&mut self, meeting_id: i32, user_id: i32) -> QueryResult<Participant> {
+        meeting_participants::table
+            .find((meeting_id, user_id))
+            .first::<Participant>(self)
+    }
+
+    fn list_participants(&mut self, meeting_id: i32) -> QueryResult<Vec<ParticipantSummary>> {
+        meeting_participants::table
+            .inner_join(users::table)
+            .filter(meeting_participants::meeting_id.eq(meeting_id))
+            .select((users::id, users::name, meeting_participants::role))
+            .order((meeting_participants::joined_at.asc(), users::id.asc()))
+            .load::<ParticipantSummary>(self)
+    }
+
+    fn add_participant(&mut self, participant: NewParticipant) -> QueryResult<Participant> {
+        diesel::insert_into(meeting_participants::table)
+            .values(&participant)
+            .get_result(self)
+    }
+
+    fn remove_participant(&mut self, meeting_id: i32, user_id: i32) -> QueryResult<usize> {
+        diesel::delete(meeting_participants::table.find((meeting_id, user_id))).execute(self)
+    }
+
+    fn create_invitation(&mut self, invitation: NewInvitation) -> QueryResult<Invitation> {
+        diesel::insert_into(meeting_invitations::table)
+            .values(&invitation)
+            .get_result(self)
+    }
+
+    fn get_invitation(&mut self, invitation_id: i32) -> QueryResult<Invitation> {
+        meeting_invitations::table
+            .find(invitation_id)
+            .first::<Invitation>(self)
+    }
+
+    fn list_pending_invitations(&mut self, user: &User) -> QueryResult<Vec<Invitation>> {
+        meeting_invitations::table
+            .filter(meeting_invitations::status.eq(InvitationStatus::Pending))
+            .filter(
+                meeting_invitations::invitee_user_id
+                    .eq(user.id)
+                    .or(lower(meeting_invitations::invitee_email).eq(user.email.to_lowercase())),
+            )
+            .order((
+                meeting_invitations::created_at.desc(),
+                meeting_invitations::id.desc(),
+            ))
+            .load::<Invitation>(self)
+    }
+
+    fn accept_invitation(&mut self, invitation_id: i32, user_id: i32) -> QueryResult<Participant> {
+        self.transaction(|conn| {
+            let invitation: Invitation = diesel::update(
+                meeting_invitations::table
+                    .find(invitation_id)
+                    .filter(meeting_invitations::status.eq(InvitationStatus::Pending)),
+            )
+            .set(meeting_invitations::status.eq(InvitationStatus::Accepted))
+            .get_result(conn)?;
+
+            diesel::insert_into(meeting_participants::table)
+                .values(&NewParticipant {
+                    meeting_id: invitation.meeting_id,
+                    user_id,
+                    role: invitation.role,
+                })
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            conn.get_participant(invitation.meeting_id, user_id)
+        })
+    }
+
+    fn decline_invitation(&mut self, invitation_id: i32) -> QueryResult<Invitation> {
+        diesel::update(
+            meeting_invitations::table
+                .find(invitation_id)
+                .filter(meeting_invitations::status.eq(InvitationStatus::Pending)),
+        )
+        .set(meeting_invitations::status.eq(InvitationStatus::Declined))
+        .get_result(self)
+    }
+
+    fn create_join_link(&mut self, link: NewJoinLink) -> QueryResult<JoinLink> {
+        diesel::insert_into(meeting_join_links::table)
+            .values(&link)
+            .get_result(self)
+    }
+
+    fn get_join_link(&mut self, link_id: i32) -> QueryResult<JoinLink> {
+        meeting_join_links::table
+            .find(link_id)
+            .first::<JoinLink>(self)
+    }
+
+    fn list_join_links(&mut self, meeting_id: i32) -> QueryResult<Vec<JoinLink>> {
+        meeting_join_links::table
+            .filter(meeting_join_links::meeting_id.eq(meeting_id))
+            .order((
+                meeting_join_links::created_at.desc(),
+                meeting_join_links::id.desc(),
+            ))
+            .load::<JoinLink>(self)
+    }
+
+    fn revoke_join_link(&mut self, link_id: i32, now: NaiveDateTime) -> QueryResult<JoinLink> {
+        diesel::update(meeting_join_links::table.find(link_id))
+            .set(meeting_join_links::revoked_at.eq(now))
+            .get_result(self)
+    }
+
+    fn redeem_join_link(
+        &mut self,
+        link_id: i32,
+        user_id: i32,
+        now: NaiveDateTime,
+    ) -> QueryResult<Option<Participant>> {
+        self.transaction(|conn| {
+            // The use is counted in the same statement that checks the limit,
+            // so concurrent redemptions cannot overshoot `max_uses`.
+            let link: Option<JoinLink> =
+                diesel::update(
+                    meeting_join_links::table
+                        .find(link_id)
+                        .filter(meeting_join_links::revoked_at.is_null())
+                        .filter(meeting_join_links::expires_at.gt(now))
+                        .filter(meeting_join_links::max_uses.is_null().or(
+                            meeting_join_links::max_uses.gt(meeting_join_links::uses.nullable()),
+                        )),
+                )
+                .set(meeting_join_links::uses.eq(meeting_join_links::uses + 1))
+                .get_result(conn)
+                .optional()?;
+            let Some(link) = link else {
+                return Ok(None);
+            };
+
+            diesel::insert_into(meeting_participants::table)
+                .values(&NewParticipant {
+                    meeting_id: link.meeting_id,
+                    user_id,
+                    role: link.role,
+                })
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            conn.get_participant(link.meeting_id, user_id).map(Some)
+        })
+    }
+
+    fn create_message(&mut self, new_message: NewMessage) -> QueryResult<Message> {
+        diesel::insert_into(messages::table)
+            .values(&new_message)
+            .get_result(self)
+    }
+
+    fn get_message_page(
+        &mut self,
+        meeting_id: i32,
+        page: &MessagePageRequest,
+    ) -> QueryResult<MessagePage> {
+        let mut query = messages::table
+            .filter(messages::meeting_id.eq(meeting_id))
+            .into_boxed();
+        query = match (page.direction, page.cursor) {
+            (PageDirection::Before, Some(cursor)) => query.filter(
+                messages::timestamp
+                    .lt(cursor.timestamp)
+                    .or(messages::timestamp
+                        .eq(cursor.timestamp)
+                        .and(messages::id.lt(cursor.id))),
+            ),
+            (PageDirection::After, Some(cursor)) => query.filter(
+                messages::timestamp
+                    .gt(cursor.timestamp)
+                    .or(messages::timestamp
+                        .eq(cursor.timestamp)
+                        .and(messages::id.gt(cursor.id))),
+            ),
+            (_, None) => query,
+        };
+        query = match page.direction {
+            PageDirection::Before => query.order((messages::timestamp.desc(), messages::id.desc())),
+            PageDirection::After => query.order((messages::timestamp.asc(), messages::id.asc())),
+        };
+
+        // One extra row tells whether another page follows.
+        let mut messages = query.limit(page.limit + 1).load::<Message>(self)?;
+        let more = messages.len() as i64 > page.limit;
+        messages.truncate(page.limit as usize);
+        if page.direction == PageDirection::Before {
+            messages.reverse();
+        }
+        let last = match page.direction {
+            PageDirection::Before => messages.first(),
+            PageDirection::After => messages.last(),
+        };
+        let next_cursor = last
+            .filter(|_| more)
+            .map(|message| MessageCursor::of(message).encode());
+        Ok(MessagePage {
+            messages,
+            next_cursor,
+        })
+    }
+
+    fn get_message(&mut self, meeting_id: i32, message_id: i32) -> QueryResult<Message> {
+        messages::table
+            .find(message_id)
+            .filter(messages::meeting_id.eq(meeting_id))
+            .first::<Message>(self)
+    }
+
+    fn edit_message(
+        &mut self,
+        message_id: i32,
+        editor_id: i32,
+        content: &str,
+        now: NaiveDateTime,
+    ) -> QueryResult<Message> {
+        self.transaction(|conn| {
+            let previous: String = messages::table
+                .find(message_id)
+                .filter(messages::deleted_at.is_null())
+                .select(messages::content)
+                .for_update()
+                .first(conn)?;
+            diesel::insert_into(message_revisions::table)
+                .values(&NewMessageRevision {
+                    message_id,
+                    content: previous,
+                    action: RevisionAction::Edit,
+                    changed_by: editor_id,
+                    created_at: now,
+                })
+                .execute(conn)?;
+            diesel::update(messages::table.find(message_id))
+                .set((
+                    messages::content.eq(content),
+                    messages::edited_at.eq(Some(now)),
+                ))
+                .get_result(conn)
+        })
+    }
+
+    fn delete_message(
+        &mut self,
+        message_id: i32,
+        deleter_id: i32,
+        now: NaiveDateTime,
+    ) -> QueryResult<Message> {
+        self.transaction(|conn| {
+            let previous: String = messages::table
+                .find(message_id)
+                .filter(messages::deleted_at.is_null())
+                .select(messages::content)
+                .for_update()
+                .first(conn)?;
+            diesel::insert_into(message_revisions::table)
+                .values(&NewMessageRevision {
+                    message_id,
+                    content: previous,
+                    action: RevisionAction::Delete,
+                    changed_by: deleter_id,
+                    created_at: now,
+                })
+                .execute(conn)?;
+            diesel::update(messages::table.find(message_id))
+                .set((messages::content.eq(""), messages::deleted_at.eq(Some(now))))
+                .get_result(conn)
+        })
+    }
+
+    fn list_message_revisions(&mut self, message_id: i32) -> QueryResult<Vec<MessageRevision>> {
+        message_revisions::table
+            .filter(message_revisions::message_id.eq(message_id))
+            .order(message_revisions::id.asc())
+            .load::<MessageRevision>(self)
+    }
+
+    fn mark_read(
+        &mut self,
+        meeting_id: i32,
+        user_id: i32,
+        message_id: i32,
+        now: NaiveDateTime,
+    ) -> QueryResult<ReadReceipt> {
+        self.transaction(|conn| {
+            let timestamp: NaiveDateTime = messages::table
+                .find(message_id)
+                .filter(messages::meeting_id.eq(meeting_id))
+                .select(messages::timestamp)
+                .first(conn)?;
+
+            diesel::insert_into(message_reads::table)
+                .values(&ReadReceipt {
+                    meeting_id,
+                    user_id,
+                    last_read_message_id: message_id,
+                    last_read_timestamp: timestamp,
+                    read_at: now,
+                })
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            diesel::update(
+                message_reads::table.find((meeting_id, user_id)).filter(
+                    message_reads::last_read_timestamp.lt(timestamp).or(
+                        message_reads::last_read_timestamp
+                            .eq(timestamp)
+                            .and(message_reads::last_read_message_id.lt(message_id)),
+                    ),
+                ),
+            )
+            .set((
+                message_reads::last_read_message_id.eq(message_id),
+                message_reads::last_read_timestamp.eq(timestamp),
+                message_reads::read_at.eq(now),
+            ))
+            .execute(conn)?;
+
+            message_reads::table
+                .find((meeting_id, user_id))
+                .first::<ReadReceipt>(conn)
+        })
+    }
+
+    fn list_read_receipts(&mut self, meeting_id: i32) -> QueryResult<Vec<ReadReceipt>> {
+        message_reads::table
+            .filter(message_reads::meeting_id.eq(meeting_id))
+            .order(message_reads::user_id.asc())
+            .load::<ReadReceipt>(self)
+    }
+
+    fn unread_counts(
+        &mut self,
+        user_id: i32,
+        meeting_ids: &[i32],
+    ) -> QueryResult<HashMap<i32, i64>> {
+        let joined: Vec<i32> = meeting_participants::table
+            .filter(meeting_participants::user_id.eq(user_id))
+            .filter(meeting_participants::meeting_id.eq_any(meeting_ids))
+            .select(meeting_participants::meeting_id)
+            .load(self)?;
+
+        let unread: Vec<(i32, i64)> = messages::table
+            .left_join(
+                message_reads::table.on(message_reads::meeting_id
+                    .eq(messages::meeting_id)
+                    .and(message_reads::user_id.eq(user_id))),
+            )
+            .filter(messages::meeting_id.eq_any(&joined))
+            .filter(messages::sender_id.ne(user_id))
+            .filter(messages::deleted_at.is_null())
+            .filter(
+                message_reads::last_read_message_id
+                    .is_null()
+                    .or(messages::timestamp
+                        .nullable()
+                        .gt(message_reads::last_read_timestamp.nullable())
+                        .or(messages::timestamp
+                            .nullable()
+                            .eq(message_reads::last_read_timestamp.nullable())
+                            .and(
+                                messages::id
+                                    .nullable()
+                                    .gt(message_reads::last_read_message_id.nullable()),
+                            ))),
+            )
+            .group_by(messages::meeting_id)
+            .select((messages::meeting_id, diesel::dsl::count_star()))
+            .load(self)?;
+
+        let mut counts: HashMap<i32, i64> = joined.into_iter().map(|id| (id, 0)).collect();
+        counts.extend(unread);
+        Ok(counts)
+    }
+}
@@ -0,0 +1,1333 @@
+use crate::metrics::Metrics;
+use actix::prelude::*;
+use actix_web_actors::ws::CloseCode;
+use protocol::{
+    LeaveReason, Peer, PresenceEvent, PresenceStatus, Roster, RosterEntry, ServerFrame,
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A text frame pushed from the registry to a connected session.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct RoomFrame(pub String);
+
+/// Asks a session to close its connection, e.g. because the server is
+/// shutting down or its login session was revoked.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct CloseSession {
+    pub code: CloseCode,
+    pub reason: String,
+}
+
+/// Tells a session it was taken out of a meeting room because its user was
+/// removed from the meeting.
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "()")]
+pub struct Evicted {
+    pub meeting_id: i32,
+}
+
+/// Registers a new session and returns the id the registry assigned to it.
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct Connect {
+    pub addr: Recipient<RoomFrame>,
+    pub close: Recipient<CloseSession>,
+    pub evicted: Recipient<Evicted>,
+    pub user_id: i32,
+    /// The login session (`sid`) of the access token the session opened
+    /// with.
+    pub auth_session_id: i32,
+}
+
+/// Removes a session from the registry and from whatever meeting it was in.
+/// With `LeaveReason::Disconnected` the session stays in its meeting's
+/// roster as reconnecting until the grace period ends.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Disconnect {
+    pub session_id: usize,
+    pub reason: LeaveReason,
+}
+
+/// Moves a session into a meeting room, leaving any room it was in before.
+#[derive(Message)]
+#[rtype(result = "RoomSnapshot")]
+pub struct Join {
+    pub session_id: usize,
+    pub meeting_id: i32,
+}
+
+/// What a session sees when it joins a room.
+pub struct RoomSnapshot {
+    /// The other sessions connected to the room.
+    pub peers: Vec<Peer>,
+    pub roster: Roster,
+}
+
+/// Takes a session out of its current meeting room.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Leave {
+    pub session_id: usize,
+}
+
+/// Updates a session's microphone and camera state. Fields left `None`
+/// keep their value.
+#[derive(Message)]
+#[rtype(result = "Result<(), RelayError>")]
+pub struct SetMedia {
+    pub session_id: usize,
+    pub muted: Option<bool>,
+    pub camera: Option<bool>,
+}
+
+/// Replies with the roster of a meeting, empty if nobody is connected.
+#[derive(Message)]
+#[rtype(result = "Roster")]
+pub struct GetRoster {
+    pub meeting_id: i32,
+}
+
+/// Fans a frame out to every other session in the sender's meeting room.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Broadcast {
+    pub session_id: usize,
+    pub frame: RoomFrame,
+}
+
+/// Pushes a frame to every session in a meeting room, including the sender.
+/// Used for server-originated events such as stored chat messages.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Publish {
+    pub meeting_id: i32,
+    pub frame: RoomFrame,
+}
+
+/// Delivers a frame to one specific session, provided both sessions are in
+/// the same meeting room.
+#[derive(Message)]
+#[rtype(result = "Result<(), RelayError>")]
+pub struct SendTo {
+    pub session_id: usize,
+    pub target: usize,
+    pub frame: RoomFrame,
+}
+
+/// Takes every session of a user out of a meeting room and sends each of
+/// them `Evicted`. Used when the user is removed from the meeting.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Evict {
+    pub meeting_id: i32,
+    pub user_id: i32,
+}
+
+/// Takes every session out of a meeting room and sends each of them
+/// `Evicted`, then forgets the room. Used when the meeting is deleted.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CloseRoom {
+    pub meeting_id: i32,
+}
+
+/// Closes the sessions opened with a login session that was revoked: the
+/// one with `auth_session_id`, or every one of the user's when it is `None`.
+/// Replies with the number of sessions told to close.
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct Revoke {
+    pub user_id: i32,
+    pub auth_session_id: Option<i32>,
+}
+
+/// Closes every connected session, and any that connect afterwards, with
+/// the given reason. Replies with the number of sessions told to close.
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct Shutdown {
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelayError {
+    NotInMeeting,
+    TargetNotInMeeting,
+}
+
+impl std::fmt::Display for RelayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RelayError::NotInMeeting => write!(f, "Join a meeting first"),
+            RelayError::TargetNotInMeeting => write!(f, "Target is not in this meeting"),
+        }
+    }
+}
+
+struct SessionEntry {
+    addr: Recipient<RoomFrame>,
+    close: Recipient<CloseSession>,
+    evicted: Recipient<Evicted>,
+    user_id: i32,
+    auth_session_id: i32,
+    muted: bool,
+    camera: bool,
+}
+
+/// A session whose connection dropped, kept in its meeting's roster until
+/// its user comes back or the grace period ends.
+struct DroppedSession {
+    meeting_id: i32,
+    entry: RosterEntry,
+    expiry: SpawnHandle,
+}
+
+/// Tracks which sessions are connected and which meeting each one is in.
+#[derive(Default)]
+pub struct RoomRegistry {
+    sessions: HashMap<usize, SessionEntry>,
+    memberships: HashMap<usize, i32>,
+    rooms: HashMap<i32, HashSet<usize>>,
+    dropped: HashMap<usize, DroppedSession>,
+    next_id: usize,
+    metrics: Arc<Metrics>,
+    /// How long a dropped session stays in the roster as reconnecting.
+    reconnect_grace: Duration,
+    /// Set once `Shutdown` was received.
+    shutdown_reason: Option<String>,
+}
+
+impl RoomRegistry {
+    /// A registry that reports its connection and room sizes to `metrics`.
+    pub fn new(metrics: Arc<Metrics>, reconnect_grace: Duration) -> Self {
+        RoomRegistry {
+            metrics,
+            reconnect_grace,
+            ..RoomRegistry::default()
+        }
+    }
+
+    fn roster_entry(&self, session_id: usize) -> Option<RosterEntry> {
+        self.sessions.get(&session_id).map(|entry| RosterEntry {
+            peer: Peer {
+                session_id,
+                user_id: entry.user_id,
+            },
+            status: PresenceStatus::Connected,
+            muted: entry.muted,
+            camera: entry.camera,
+        })
+    }
+
+    fn roster(&self, meeting_id: i32) -> Roster {
+        let connected = self
+            .rooms
+            .get(&meeting_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|session_id| self.roster_entry(*session_id));
+        let reconnecting = self
+            .dropped
+            .values()
+            .filter(|dropped| dropped.meeting_id == meeting_id)
+            .map(|dropped| dropped.entry);
+        let mut entries: Vec<RosterEntry> = connected.chain(reconnecting).collect();
+        entries.sort_by_key(|entry| entry.peer.session_id);
+        Roster {
+            meeting_id,
+            entries,
+        }
+    }
+
+    /// Tells every session in a meeting room, except `peer` itself, about a
+    /// change to `peer`.
+    fn announce(&self, meeting_id: i32, peer: Peer, event: PresenceEvent) {
+        if self.shutdown_reason.is_some() {
+            return;
+        }
+        let Some(members) = self.rooms.get(&meeting_id) else {
+            return;
+        };
+        let frame = RoomFrame(
+            ServerFrame::Presence {
+                meeting_id,
+                peer,
+                event,
+            }
+            .to_text(),
+        );
+        for member in members.iter().filter(|id| **id != peer.session_id) {
+            if let Some(entry) = self.sessions.get(member) {
+                entry.addr.do_send(frame.clone());
+            }
+        }
+    }
+
+    fn leave_room(&mut self, session_id: usize, reason: LeaveReason, ctx: &mut Context<Self>) {
+        let Some(meeting_id) = self.memberships.remove(&session_id) else {
+            return;
+        };
+        if let Some(members) = self.rooms.get_mut(&meeting_id) {
+            members.remove(&session_id);
+            self.metrics.set_meeting_sessions(meeting_id, members.len());
+            if members.is_empty() {
+                self.rooms.remove(&meeting_id);
+            }
+        }
+        tracing::debug!(session_id, meeting_id, ?reason, "session left meeting");
+
+        let Some(entry) = self.roster_entry(session_id) else {
+            return;
+        };
+        if reason == LeaveReason::Disconnected && !self.reconnect_grace.is_zero() {
+            let expiry = ctx.run_later(self.reconnect_grace, move |act, _| {
+                act.expire(session_id);
+            });
+            self.dropped.insert(
+                session_id,
+                DroppedSession {
+                    meeting_id,
+                    entry: RosterEntry {
+                        status: PresenceStatus::Reconnecting,
+                        ..entry
+                    },
+                    expiry,
+                },
+            );
+            self.announce(meeting_id, entry.peer, PresenceEvent::Reconnecting);
+        } else {
+            self.announce(meeting_id, entry.peer, PresenceEvent::Left { reason });
+        }
+    }
+
+    /// Ends the grace period of a dropped session whose user did not come
+    /// back.
+    fn expire(&mut self, session_id: usize) {
+        if let Some(dropped) = self.dropped.remove(&session_id) {
+            self.announce(
+                dropped.meeting_id,
+                dropped.entry.peer,
+                PresenceEvent::Left {
+                    reason: LeaveReason::Disconnected,
+                },
+            );
+        }
+    }
+
+    /// Takes the dropped sessions of a user who joined the meeting again
+    /// out of the roster. Returns the latest of them, which the new session
+    /// takes over; the others are reported as gone.
+    fn resume(
+        &mut self,
+        meeting_id: i32,
+        user_id: i32,
+        ctx: &mut Context<Self>,
+    ) -> Option<RosterEntry> {
+        let mut resumed = self.dropped_sessions(meeting_id, user_id);
+        resumed.sort_unstable_by(|a, b| b.cmp(a));
+        let mut latest = None;
+        for session_id in resumed {
+            let Some(dropped) = self.dropped.remove(&session_id) else {
+                continue;
+            };
+            ctx.cancel_future(dropped.expiry);
+            if latest.is_none() {
+                latest = Some(dropped.entry);
+            } else {
+                self.announce(
+                    meeting_id,
+                    dropped.entry.peer,
+                    PresenceEvent::Left {
+                        reason: LeaveReason::Disconnected,
+                    },
+                );
+            }
+        }
+        latest
+    }
+
+    fn dropped_sessions(&self, meeting_id: i32, user_id: i32) -> Vec<usize> {
+        self.dropped
+            .iter()
+            .filter(|(_, dropped)| {
+                dropped.meeting_id == meeting_id && dropped.entry.peer.user_id == user_id
+            })
+            .map(|(session_id, _)| *session_id)
+            .collect()
+    }
+}
+
+impl Actor for RoomRegistry {
+    type Context = Context<Self>;
+}
+
+impl Handler<Connect> for RoomRegistry {
+    type Result = usize;
+
+    fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> Self::Result {
+        self.next_id += 1;
+        let session_id = self.next_id;
+        // A handshake that was already under way when shutdown began.
+        if let Some(reason) = &self.shutdown_reason {
+            msg.close.do_send(CloseSession {
+                code: CloseCode::Restart,
+                reason: reason.clone(),
+            });
+        }
+        self.sessions.insert(
+            session_id,
+            SessionEntry {
+                addr: msg.addr,
+                close: msg.close,
+                evicted: msg.evicted,
+                user_id: msg.user_id,
+                auth_session_id: msg.auth_session_id,
+                muted: false,
+                camera: false,
+            },
+        );
+        self.metrics.ws_connections.inc();
+        session_id
+    }
+}
+
+impl Handler<Disconnect> for RoomRegistry {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, ctx: &mut Context<Self>) {
+        self.leave_room(msg.session_id, msg.reason, ctx);
+        if self.sessions.remove(&msg.session_id).is_some() {
+            self.metrics.ws_connections.dec();
+        }
+    }
+}
+
+impl Handler<Join> for RoomRegistry {
+    type Result = MessageResult<Join>;
+
+    fn handle(&mut self, msg: Join, ctx: &mut Context<Self>) -> Self::Result {
+        self.leave_room(msg.session_id, LeaveReason::Left, ctx);
+        let Some(user_id) = self.sessions.get(&msg.session_id).map(|e| e.user_id) else {
+            return MessageResult(RoomSnapshot {
+                peers: Vec::new(),
+                roster: self.roster(msg.meeting_id),
+            });
+        };
+        let resumed = self.resume(msg.meeting_id, user_id, ctx);
+        if let (Some(resumed), Some(entry)) = (resumed, self.sessions.get_mut(&msg.session_id)) {
+            entry.muted = resumed.muted;
+            entry.camera = resumed.camera;
+        }
+
+        self.memberships.insert(msg.session_id, msg.meeting_id);
+        let members = self.rooms.entry(msg.meeting_id).or_default();
+        let peers = members
+            .iter()
+            .filter_map(|id| {
+                self.sessions.get(id).map(|entry| Peer {
+                    session_id: *id,
+                    user_id: entry.user_id,
+                })
+            })
+            .collect();
+        members.insert(msg.session_id);
+        self.metrics
+            .set_meeting_sessions(msg.meeting_id, members.len());
+        tracing::debug!(
+            session_id = msg.session_id,
+            meeting_id = msg.meeting_id,
+            "session joined meeting"
+        );
+        self.announce(
+            msg.meeting_id,
+            Peer {
+                session_id: msg.session_id,
+                user_id,
+            },
+            PresenceEvent::Joined {
+                replaces: resumed.map(|entry| entry.peer.session_id),
+            },
+        );
+        MessageResult(RoomSnapshot {
+            peers,
+            roster: self.roster(msg.meeting_id),
+        })
+    }
+}
+
+impl Handler<Leave> for RoomRegistry {
+    type Result = ();
+
+    fn handle(&mut self, msg: Leave, ctx: &mut Context<Self>) {
+        self.leave_room(msg.session_id, LeaveReason::Left, ctx);
+    }
+}
+
+impl Handler<SetMedia> for RoomRegistry {
+    type Result = Result<(), RelayError>;
+
+    fn handle(&mut self, msg: SetMedia, _: &mut Context<Self>) -> Self::Result {
+        let meeting_id = *self
+            .memberships
+            .get(&msg.session_id)
+            .ok_or(RelayError::NotInMeeting)?;
+        let Some(entry) = self.sessions.get_mut(&msg.session_id) else {
+            return Err(RelayError::NotInMeeting);
+        };
+        let mut events = Vec::new();
+        if let Some(muted) = msg.muted.filter(|muted| *muted != entry.muted) {
+            entry.muted = muted;
+            events.push(if muted {
+                PresenceEvent::Muted
+            } else {
+                PresenceEvent::Unmuted
+            });
+        }
+        if let Some(camera) = msg.camera.filter(|camera| *camera != entry.camera) {
+            entry.camera = camera;
+            events.push(if camera {
+                PresenceEvent::CameraOn
+            } else {
+                PresenceEvent::CameraOff
+            });
+        }
+
+        let peer = Peer {
+            session_id: msg.session_id,
+            user_id: entry.user_id,
+        };
+        for event in events {
+            self.announce(meeting_id, peer, event);
+        }
+        Ok(())
+    }
+}
+
+impl Handler<GetRoster> for RoomRegistry {
+    type Result = MessageResult<GetRoster>;
+
+    fn handle(&mut self, msg: GetRoster, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.roster(msg.meeting_id))
+    }
+}
+
+impl Handler<Broadcast> for RoomRegistry {
+    type Result = ();
+
+    fn handle(&mut self, msg: Broadcast, _: &mut Context<Self>) {
+        let Some(meeting_id) = self.memberships.get(&msg.session_id) else {
+            return;
+        };
+        let Some(members) = self.rooms.get(meeting_id) else {
+            return;
+        };
+
+        for member in members.iter().filter(|id| **id != msg.session_id) {
+            if let Some(entry) = self.sessions.get(member) {
+                entry.addr.do_send(msg.frame.clone());
+            }
+        }
+    }
+}
+
+impl Handler<Publish> for RoomRegistry {
+    type Result = ();
+
+    fn handle(&mut self, msg: Publish, _: &mut Context<Self>) {
+        let Some(members) = self.rooms.get(&msg.meeting_id) else {
+            return;
+        };
+
+        for member in members {
+            if let Some(entry) = self.sessions.get(member) {
+                entry.addr.do_send(msg.frame.clone());
+            }
+        }
+    }
+}
+
+impl Handler<SendTo> for RoomRegistry {
+    type Result = Result<(), RelayError>;
+
+    fn handle(&mut self, msg: SendTo, _: &mut Context<Self>) -> Self::Result {
+        let meeting_id = self
+            .memberships
+            .get(&msg.session_id)
+            .ok_or(RelayError::NotInMeeting)?;
+        if self.memberships.get(&msg.target) != Some(meeting_id) {
+            return Err(RelayError::TargetNotInMeeting);
+        }
+
+        if let Some(entry) = self.sessions.get(&msg.target) {
+            entry.addr.do_send(msg.frame);
+        }
+        Ok(())
+    }
+}
+
+impl Handler<Evict> for RoomRegistry {
+    type Result = ();
+
+    fn handle(&mut self, msg: Evict, ctx: &mut Context<Self>) {
+        for session_id in self.dropped_sessions(msg.meeting_id, msg.user_id) {
+            if let Some(dropped) = self.dropped.remove(&session_id) {
+                ctx.cancel_future(dropped.expiry);
+                self.announce(
+                    msg.meeting_id,
+                    dropped.entry.peer,
+                    PresenceEvent::Left {
+                        reason: LeaveReason::Removed,
+                    },
+                );
+            }
+        }
+
+        let Some(members) = self.rooms.get(&msg.meeting_id) else {
+            return;
+        };
+        let evicted: Vec<usize> = members
+            .iter()
+            .copied()
+            .filter(|id| {
+                self.sessions
+                    .get(id)
+                    .is_some_and(|entry| entry.user_id == msg.user_id)
+            })
+            .collect();
+
+        for session_id in evicted {
+            if let Some(entry) = self.sessions.get(&session_id) {
+                entry.evicted.do_send(Evicted {
+                    meeting_id: msg.meeting_id,
+                });
+            }
+            self.leave_room(session_id, LeaveReason::Removed, ctx);
+        }
+    }
+}
+
+impl Handler<CloseRoom> for RoomRegistry {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseRoom, ctx: &mut Context<Self>) {
+        let dropped: Vec<usize> = self
+            .dropped
+            .iter()
+            .filter(|(_, dropped)| dropped.meeting_id == msg.meeting_id)
+            .map(|(session_id, _)| *session_id)
+            .collect();
+        for session_id in dropped {
+            if let Some(dropped) = self.dropped.remove(&session_id) {
+                ctx.cancel_future(dropped.expiry);
+            }
+        }
+
+        // Nobody is left to hear about the others leaving, so the members
+        // are taken out without presence announcements.
+        let Some(members) = self.rooms.remove(&msg.meeting_id) else {
+            return;
+        };
+        for session_id in members {
+            self.memberships.remove(&session_id);
+            if let Some(entry) = self.sessions.get(&session_id) {
+                entry.evicted.do_send(Evicted {
+                    meeting_id: msg.meeting_id,
+                });
+            }
+        }
+        self.metrics.set_meeting_sessions(msg.meeting_id, 0);
+        tracing::debug!(meeting_id = msg.meeting_id, "meeting room closed");
+    }
+}
+
+impl Handler<Revoke> for RoomRegistry {
+    type Result = usize;
+
+    fn handle(&mut self, msg: Revoke, _: &mut Context<Self>) -> Self::Result {
+        let revoked = self.sessions.values().filter(|entry| {
+            entry.user_id == msg.user_id
+                && msg
+                    .auth_session_id
+                    .is_none_or(|id| id == entry.auth_session_id)
+        });
+        let mut closed = 0;
+        for entry in revoked {
+            entry.close.do_send(CloseSession {
+                code: CloseCode::Policy,
+                reason: "Session has been revoked".to_string(),
+            });
+            closed += 1;
+        }
+        closed
+    }
+}
+
+impl Handler<Shutdown> for RoomRegistry {
+    type Result = usize;
+
+    fn handle(&mut self, msg: Shutdown, _: &mut Context<Self>) -> Self::Result {
+        for entry in self.sessions.values() {
+            entry.close.do_send(CloseSession {
+                code: CloseCode::Restart,
+                reason: msg.reason.clone(),
+            });
+        }
+        self.shutdown_reason = Some(msg.reason);
+        self.sessions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Something the registry sent to a session.
+    #[derive(Debug, Clone, PartialEq)]
+    enum Received {
+        Frame(ServerFrame),
+        Close(String),
+        Evicted(i32),
+    }
+
+    /// Stands in for a `WsSession` and keeps everything it is sent.
+    #[derive(Default)]
+    struct Recorder {
+        received: Vec<Received>,
+    }
+
+    impl Actor for Recorder {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<RoomFrame> for Recorder {
+        type Result = ();
+
+        fn handle(&mut self, frame: RoomFrame, _: &mut Context<Self>) {
+            let frame = ServerFrame::from_text(&frame.0).expect("registry sent a valid frame");
+            self.received.push(Received::Frame(frame));
+        }
+    }
+
+    impl Handler<CloseSession> for Recorder {
+        type Result = ();
+
+        fn handle(&mut self, msg: CloseSession, _: &mut Context<Self>) {
+            self.received.push(Received::Close(msg.reason));
+        }
+    }
+
+    impl Handler<Evicted> for Recorder {
+        type Result = ();
+
+        fn handle(&mut self, msg: Evicted, _: &mut Context<Self>) {
+            self.received.push(Received::Evicted(msg.meeting_id));
+        }
+    }
+
+    /// Replies with what the recorder received since the last `Take`. The
+    /// mailbox is in order, so this also waits for everything the registry
+    /// sent before.
+    #[derive(Message)]
+    #[rtype(result = "Vec<Received>")]
+    struct Take;
+
+    impl Handler<Take> for Recorder {
+        type Result = MessageResult<Take>;
+
+        fn handle(&mut self, _: Take, _: &mut Context<Self>) -> Self::Result {
+            MessageResult(std::mem::take(&mut self.received))
+        }
+    }
+
+    struct TestSession {
+        id: usize,
+        recorder: Addr<Recorder>,
+    }
+
+    impl TestSession {
+        async fn received(&self) -> Vec<Received> {
+            self.recorder.send(Take).await.unwrap()
+        }
+    }
+
+    fn registry(reconnect_grace: Duration) -> Addr<RoomRegistry> {
+        RoomRegistry::new(Arc::new(Metrics::default()), reconnect_grace).start()
+    }
+
+    async fn connect(registry: &Addr<RoomRegistry>, user_id: i32) -> TestSession {
+        login(registry, user_id, 0).await
+    }
+
+    /// Connects a session opened with the given login session.
+    async fn login(
+        registry: &Addr<RoomRegistry>,
+        user_id: i32,
+        auth_session_id: i32,
+    ) -> TestSession {
+        let recorder = Recorder::default().start();
+        let id = registry
+            .send(Connect {
+                addr: recorder.clone().recipient(),
+                close: recorder.clone().recipient(),
+                evicted: recorder.clone().recipient(),
+                user_id,
+                auth_session_id,
+            })
+            .await
+            .unwrap();
+        TestSession { id, recorder }
+    }
+
+    /// Connects a session and joins it to a meeting.
+    async fn join(registry: &Addr<RoomRegistry>, user_id: i32, meeting_id: i32) -> TestSession {
+        let session = connect(registry, user_id).await;
+        registry
+            .send(Join {
+                session_id: session.id,
+                meeting_id,
+            })
+            .await
+            .unwrap();
+        session
+    }
+
+    fn text(frame: &ServerFrame) -> RoomFrame {
+        RoomFrame(frame.to_text())
+    }
+
+    fn relay(payload: &str) -> ServerFrame {
+        ServerFrame::Relay {
+            from: Peer {
+                session_id: 0,
+                user_id: 0,
+            },
+            payload: payload.into(),
+        }
+    }
+
+    fn peer(session: &TestSession, user_id: i32) -> Peer {
+        Peer {
+            session_id: session.id,
+            user_id,
+        }
+    }
+
+    fn presence(meeting_id: i32, peer: Peer, event: PresenceEvent) -> Received {
+        Received::Frame(ServerFrame::Presence {
+            meeting_id,
+            peer,
+            event,
+        })
+    }
+
+    async fn roster_sessions(registry: &Addr<RoomRegistry>, meeting_id: i32) -> Vec<usize> {
+        let roster = registry.send(GetRoster { meeting_id }).await.unwrap();
+        roster
+            .entries
+            .iter()
+            .map(|entry| entry.peer.session_id)
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn evicted_sessions_leave_the_room() {
+        let registry = registry(Duration::ZERO);
+        let host = join(&registry, 1, 7).await;
+        let guest = join(&registry, 2, 7).await;
+        host.received().await;
+
+        registry
+            .send(Evict {
+                meeting_id: 7,
+                user_id: 2,
+            })
+            .await
+            .unwrap();
+        assert_eq!(guest.received().await, [Received::Evicted(7)]);
+        assert_eq!(
+            host.received().await,
+            [Received::Frame(ServerFrame::Presence {
+                meeting_id: 7,
+                peer: Peer {
+                    session_id: guest.id,
+                    user_id: 2,
+                },
+                event: PresenceEvent::Left {
+                    reason: LeaveReason::Removed,
+                },
+            })]
+        );
+
+        registry
+            .send(Broadcast {
+                session_id: host.id,
+                frame: text(&relay("after removal")),
+            })
+            .await
+            .unwrap();
+        assert_eq!(guest.received().await, []);
+        let from_guest = registry.send(SendTo {
+            session_id: guest.id,
+            target: host.id,
+            frame: text(&relay("still here?")),
+        });
+        assert_eq!(from_guest.await.unwrap(), Err(RelayError::NotInMeeting));
+        let to_guest = registry.send(SendTo {
+            session_id: host.id,
+            target: guest.id,
+            frame: text(&relay("still here?")),
+        });
+        assert_eq!(to_guest.await.unwrap(), Err(RelayError::TargetNotInMeeting));
+        assert_eq!(host.received().await, []);
+        assert_eq!(guest.received().await, []);
+    }
+
+    #[actix_web::test]
+    async fn closing_a_room_evicts_everyone_in_it() {
+        let registry = registry(Duration::from_secs(60));
+        let host = join(&registry, 1, 7).await;
+        let guest = join(&registry, 2, 7).await;
+        let dropped = join(&registry, 3, 7).await;
+        let elsewhere = join(&registry, 4, 8).await;
+        registry
+            .send(Disconnect {
+                session_id: dropped.id,
+                reason: LeaveReason::Disconnected,
+            })
+            .await
+            .unwrap();
+        host.received().await;
+        guest.received().await;
+
+        registry.send(CloseRoom { meeting_id: 7 }).await.unwrap();
+        assert_eq!(host.received().await, [Received::Evicted(7)]);
+        assert_eq!(guest.received().await, [Received::Evicted(7)]);
+        assert!(roster_sessions(&registry, 7).await.is_empty());
+        assert_eq!(roster_sessions(&registry, 8).await, [elsewhere.id]);
+
+        let from_host = registry.send(SendTo {
+            session_id: host.id,
+            target: guest.id,
+            frame: text(&relay("still here?")),
+        });
+        assert_eq!(from_host.await.unwrap(), Err(RelayError::NotInMeeting));
+        assert_eq!(elsewhere.received().await, []);
+    }
+
+    #[actix_web::test]
+    async fn revoking_a_login_closes_its_sessions() {
+        let registry = registry(Duration::ZERO);
+        let laptop = login(&registry, 1, 10).await;
+        let phone = login(&registry, 1, 11).await;
+        let other = login(&registry, 2, 12).await;
+        let revoked = |reason: &str| Received::Close(reason.to_string());
+
+        let closed = registry.send(Revoke {
+            user_id: 1,
+            auth_session_id: Some(10),
+        });
+        assert_eq!(closed.await.unwrap(), 1);
+        assert_eq!(
+            laptop.received().await,
+            [revoked("Session has been revoked")]
+        );
+        assert_eq!(phone.received().await, []);
+
+        let closed = registry.send(Revoke {
+            user_id: 1,
+            auth_session_id: None,
+        });
+        assert_eq!(closed.await.unwrap(), 2);
+        assert_eq!(
+            phone.received().await,
+            [revoked("Session has been revoked")]
+        );
+        assert_eq!(other.received().await, []);
+    }
+
+    #[actix_web::test]
+    async fn broadcasts_reach_the_rest_of_the_room_only() {
+        let registry = registry(Duration::ZERO);
+        let alice = join(&registry, 1, 7).await;
+        let bob = join(&registry, 2, 7).await;
+        let carol = join(&registry, 3, 7).await;
+        let elsewhere = join(&registry, 4, 8).await;
+        for session in [&alice, &bob, &carol, &elsewhere] {
+            session.received().await;
+        }
+
+        let frame = relay("hello");
+        registry
+            .send(Broadcast {
+                session_id: alice.id,
+                frame: text(&frame),
+            })
+            .await
+            .unwrap();
+        assert_eq!(alice.received().await, []);
+        assert_eq!(bob.received().await, [Received::Frame(frame.clone())]);
+        assert_eq!(carol.received().await, [Received::Frame(frame)]);
+        assert_eq!(elsewhere.received().await, []);
+    }
+
+    #[actix_web::test]
+    async fn send_to_only_reaches_sessions_in_the_same_meeting() {
+        let registry = registry(Duration::ZERO);
+        let alice = join(&registry, 1, 7).await;
+        let bob = join(&registry, 2, 7).await;
+        let elsewhere = join(&registry, 3, 8).await;
+        let lobby = connect(&registry, 4).await;
+        for session in [&alice, &bob, &elsewhere] {
+            session.received().await;
+        }
+
+        let frame = relay("offer");
+        let send = |session_id, target| {
+            registry.send(SendTo {
+                session_id,
+                target,
+                frame: text(&frame),
+            })
+        };
+        assert_eq!(send(alice.id, bob.id).await.unwrap(), Ok(()));
+        assert_eq!(
+            send(alice.id, elsewhere.id).await.unwrap(),
+            Err(RelayError::TargetNotInMeeting)
+        );
+        assert_eq!(
+            send(alice.id, lobby.id).await.unwrap(),
+            Err(RelayError::TargetNotInMeeting)
+        );
+        assert_eq!(
+            send(lobby.id, alice.id).await.unwrap(),
+            Err(RelayError::NotInMeeting)
+        );
+        assert_eq!(bob.received().await, [Received::Frame(frame.clone())]);
+        assert_eq!(alice.received().await, []);
+        assert_eq!(elsewhere.received().await, []);
+        assert_eq!(lobby.received().await, []);
+    }
+
+    #[actix_web::test]
+    async fn leaving_and_disconnecting_clean_up_the_room() {
+        let registry = registry(Duration::ZERO);
+        let alice = join(&registry, 1, 7).await;
+        let bob = join(&registry, 2, 7).await;
+        let carol = join(&registry, 3, 7).await;
+        for session in [&alice, &bob, &carol] {
+            session.received().await;
+        }
+        assert_eq!(
+            roster_sessions(&registry, 7).await,
+            [alice.id, bob.id, carol.id]
+        );
+
+        registry.send(Leave { session_id: bob.id }).await.unwrap();
+        registry
+            .send(Disconnect {
+                session_id: carol.id,
+                reason: LeaveReason::Left,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            alice.received().await,
+            [
+                presence(
+                    7,
+                    peer(&bob, 2),
+                    PresenceEvent::Left {
+                        reason: LeaveReason::Left
+                    }
+                ),
+                presence(
+                    7,
+                    peer(&carol, 3),
+                    PresenceEvent::Left {
+                        reason: LeaveReason::Left
+                    }
+                ),
+            ]
+        );
+        assert_eq!(roster_sessions(&registry, 7).await, [alice.id]);
+        carol.received().await;
+
+        // Bob is still connected, just in no room.
+        let frame = relay("anyone?");
+        registry
+            .send(Broadcast {
+                session_id: alice.id,
+                frame: text(&frame),
+            })
+            .await
+            .unwrap();
+        assert_eq!(bob.received().await, []);
+        assert_eq!(carol.received().await, []);
+        // A disconnected session cannot come back into the room.
+        registry
+            .send(Join {
+                session_id: carol.id,
+                meeting_id: 7,
+            })
+            .await
+            .unwrap();
+        assert_eq!(roster_sessions(&registry, 7).await, [alice.id]);
+
+        registry
+            .send(Disconnect {
+                session_id: alice.id,
+                reason: LeaveReason::Left,
+            })
+            .await
+            .unwrap();
+        assert!(roster_sessions(&registry, 7).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn timed_out_sessions_leave_without_a_grace_period() {
+        let registry = registry(Duration::from_secs(60));
+        let alice = join(&registry, 1, 7).await;
+        let bob = join(&registry, 2, 7).await;
+        alice.received().await;
+
+        registry
+            .send(Disconnect {
+                session_id: bob.id,
+                reason: LeaveReason::Timeout,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            alice.received().await,
+            [presence(
+                7,
+                peer(&bob, 2),
+                PresenceEvent::Left {
+                    reason: LeaveReason::Timeout
+                }
+            )]
+        );
+        assert_eq!(roster_sessions(&registry, 7).await, [alice.id]);
+    }
+
+    #[actix_web::test]
+    async fn a_reconnecting_user_takes_over_the_dropped_session() {
+        let registry = registry(Duration::from_secs(60));
+        let alice = join(&registry, 1, 7).await;
+        let dropped = join(&registry, 2, 7).await;
+        alice.received().await;
+        registry
+            .send(SetMedia {
+                session_id: dropped.id,
+                muted: Some(true),
+                camera: None,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        registry
+            .send(Disconnect {
+                session_id: dropped.id,
+                reason: LeaveReason::Disconnected,
+            })
+            .await
+            .unwrap();
+        let roster = registry.send(GetRoster { meeting_id: 7 }).await.unwrap();
+        assert_eq!(roster.entries[1].peer, peer(&dropped, 2));
+        assert_eq!(roster.entries[1].status, PresenceStatus::Reconnecting);
+
+        let resumed = join(&registry, 2, 7).await;
+        assert_eq!(
+            alice.received().await,
+            [
+                presence(7, peer(&dropped, 2), PresenceEvent::Muted),
+                presence(7, peer(&dropped, 2), PresenceEvent::Reconnecting),
+                presence(
+                    7,
+                    peer(&resumed, 2),
+                    PresenceEvent::Joined {
+                        replaces: Some(dropped.id)
+                    }
+                ),
+            ]
+        );
+        let roster = registry.send(GetRoster { meeting_id: 7 }).await.unwrap();
+        assert_eq!(roster.entries.len(), 2);
+        assert_eq!(roster.entries[1].peer, peer(&resumed, 2));
+        assert_eq!(roster.entries[1].status, PresenceStatus::Connected);
+        assert!(roster.entries[1].muted, "media state carries over");
+    }
+
+    #[actix_web::test]
+    async fn dropped_sessions_leave_once_the_grace_period_ends() {
+        let registry = registry(Duration::from_millis(20));
+        let alice = join(&registry, 1, 7).await;
+        let bob = join(&registry, 2, 7).await;
+        alice.received().await;
+
+        registry
+            .send(Disconnect {
+                session_id: bob.id,
+                reason: LeaveReason::Disconnected,
+            })
+            .await
+            .unwrap();
+        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            alice.received().await,
+            [
+                presence(7, peer(&bob, 2), PresenceEvent::Reconnecting),
+                presence(
+                    7,
+                    peer(&bob, 2),
+                    PresenceEvent::Left {
+                        reason: LeaveReason::Disconnected
+                    }
+                ),
+            ]
+        );
+        assert_eq!(roster_sessions(&registry, 7).await, [alice.id]);
+    }
+
+    #[actix_web::test]
+    async fn media_changes_reach_the_room_and_the_roster() {
+        let registry = registry(Duration::ZERO);
+        let alice = join(&registry, 1, 7).await;
+        let bob = join(&registry, 2, 7).await;
+        let elsewhere = join(&registry, 3, 8).await;
+        let lobby = connect(&registry, 4).await;
+        for session in [&alice, &bob, &elsewhere] {
+            session.received().await;
+        }
+
+        let set_media = |session_id, muted, camera| {
+            registry.send(SetMedia {
+                session_id,
+                muted,
+                camera,
+            })
+        };
+        assert_eq!(
+            set_media(bob.id, Some(true), Some(true)).await.unwrap(),
+            Ok(())
+        );
+        // Repeating the current state announces nothing.
+        assert_eq!(set_media(bob.id, Some(true), None).await.unwrap(), Ok(()));
+        assert_eq!(
+            set_media(lobby.id, Some(true), None).await.unwrap(),
+            Err(RelayError::NotInMeeting)
+        );
+        assert_eq!(
+            alice.received().await,
+            [
+                presence(7, peer(&bob, 2), PresenceEvent::Muted),
+                presence(7, peer(&bob, 2), PresenceEvent::CameraOn),
+            ]
+        );
+        assert_eq!(bob.received().await, []);
+        assert_eq!(elsewhere.received().await, []);
+
+        let roster = registry.send(GetRoster { meeting_id: 7 }).await.unwrap();
+        let media: Vec<_> = roster
+            .entries
+            .iter()
+            .map(|entry| (entry.peer.session_id, entry.muted, entry.camera))
+            .collect();
+        assert_eq!(media, [(alice.id, false, false), (bob.id, true, true)]);
+
+        set_media(bob.id, Some(false), Some(false))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            alice.received().await,
+            [
+                presence(7, peer(&bob, 2), PresenceEvent::Unmuted),
+                presence(7, peer(&bob, 2), PresenceEvent::CameraOff),
+            ]
+        );
+        let roster = registry.send(GetRoster { meeting_id: 7 }).await.unwrap();
+        assert!(!roster.entries[1].muted && !roster.entries[1].camera);
+    }
+
+    #[actix_web::test]
+    async fn joining_replies_with_the_peers_already_in_the_room() {
+        let registry = registry(Duration::ZERO);
+        let alice = join(&registry, 1, 7).await;
+        let bob = join(&registry, 2, 7).await;
+        let carol = connect(&registry, 3).await;
+
+        let snapshot = registry
+            .send(Join {
+                session_id: carol.id,
+                meeting_id: 7,
+            })
+            .await
+            .unwrap();
+        let mut peers = snapshot.peers;
+        peers.sort_by_key(|peer| peer.session_id);
+        assert_eq!(peers, [peer(&alice, 1), peer(&bob, 2)]);
+        assert_eq!(snapshot.roster.entries.len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn joining_another_meeting_leaves_the_first() {
+        let registry = registry(Duration::ZERO);
+        let alice = join(&registry, 1, 7).await;
+        let bob = join(&registry, 2, 7).await;
+        let carol = join(&registry, 3, 8).await;
+        alice.received().await;
+
+        registry
+            .send(Join {
+                session_id: bob.id,
+                meeting_id: 8,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            alice.received().await,
+            [presence(
+                7,
+                peer(&bob, 2),
+                PresenceEvent::Left {
+                    reason: LeaveReason::Left
+                }
+            )]
+        );
+        assert_eq!(
+            carol.received().await,
+            [presence(
+                8,
+                peer(&bob, 2),
+                PresenceEvent::Joined { replaces: None }
+            )]
+        );
+        assert_eq!(roster_sessions(&registry, 8).await, [bob.id, carol.id]);
+    }
+
+    #[actix_web::test]
+    async fn shutdown_closes_current_and_late_sessions() {
+        let registry = registry(Duration::ZERO);
+        let alice = join(&registry, 1, 7).await;
+        let lobby = connect(&registry, 2).await;
+        let closed = registry.send(Shutdown {
+            reason: "restarting".to_string(),
+        });
+        assert_eq!(closed.await.unwrap(), 2);
+        let late = connect(&registry, 3).await;
+
+        for session in [&alice, &lobby, &late] {
+            assert_eq!(
+                session.received().await,
+                [Received::Close("restarting".to_string())]
+            );
+        }
+    }
+}
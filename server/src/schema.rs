@@ -1,4 +1,4 @@
-use diesel::{allow_tables_to_appear_in_same_query, table};
+use diesel::{allow_tables_to_appear_in_same_query, joinable, table};
 
 table! {
     users (id) {
@@ -6,6 +6,7 @@ table! {
         name -> Varchar,
         email -> Varchar,
         password -> Varchar,
+        role -> Varchar,
     }
 }
 
@@ -27,7 +28,114 @@ table! {
         sender_id -> Int4,
         meeting_id -> Int4,
         timestamp -> Timestamp,
+        edited_at -> Nullable<Timestamp>,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
 
-allow_tables_to_appear_in_same_query!(users, meetings, messages);
+table! {
+    message_revisions (id) {
+        id -> Int4,
+        message_id -> Int4,
+        content -> Varchar,
+        action -> Varchar,
+        changed_by -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    meeting_participants (meeting_id, user_id) {
+        meeting_id -> Int4,
+        user_id -> Int4,
+        role -> Varchar,
+        joined_at -> Timestamp,
+    }
+}
+
+table! {
+    meeting_invitations (id) {
+        id -> Int4,
+        meeting_id -> Int4,
+        inviter_id -> Int4,
+        invitee_user_id -> Nullable<Int4>,
+        invitee_email -> Nullable<Varchar>,
+        role -> Varchar,
+        status -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    meeting_join_links (id) {
+        id -> Int4,
+        meeting_id -> Int4,
+        created_by -> Int4,
+        role -> Varchar,
+        max_uses -> Nullable<Int4>,
+        uses -> Int4,
+        passcode_hash -> Nullable<Varchar>,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    message_reads (meeting_id, user_id) {
+        meeting_id -> Int4,
+        user_id -> Int4,
+        last_read_message_id -> Int4,
+        last_read_timestamp -> Timestamp,
+        read_at -> Timestamp,
+    }
+}
+
+table! {
+    auth_sessions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    refresh_tokens (id) {
+        id -> Int4,
+        session_id -> Int4,
+        token_hash -> Varchar,
+        expires_at -> Timestamp,
+        rotated_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+joinable!(auth_sessions -> users (user_id));
+joinable!(refresh_tokens -> auth_sessions (session_id));
+joinable!(meetings -> users (creator_id));
+joinable!(meeting_participants -> meetings (meeting_id));
+joinable!(meeting_participants -> users (user_id));
+joinable!(meeting_invitations -> meetings (meeting_id));
+joinable!(meeting_join_links -> meetings (meeting_id));
+joinable!(meeting_join_links -> users (created_by));
+joinable!(messages -> meetings (meeting_id));
+joinable!(messages -> users (sender_id));
+joinable!(message_reads -> meetings (meeting_id));
+joinable!(message_reads -> users (user_id));
+joinable!(message_reads -> messages (last_read_message_id));
+joinable!(message_revisions -> messages (message_id));
+joinable!(message_revisions -> users (changed_by));
+
+allow_tables_to_appear_in_same_query!(
+    users,
+    meetings,
+    meeting_participants,
+    meeting_invitations,
+    meeting_join_links,
+    messages,
+    message_reads,
+    message_revisions,
+    auth_sessions,
+    refresh_tokens,
+);
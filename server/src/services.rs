@@ -1,68 +1,152 @@
-use crate::models::{Claims, Meeting, Message, NewMeeting, NewMessage, NewUser, User};
-use actix_web::error::ErrorUnauthorized;
+use crate::config::AuthConfig;
+use crate::db::{self, DbError, DbPool};
+use crate::errors::ApiError;
+use crate::models::{
+    Claims, JoinLink, JoinLinkClaims, MessageCursor, MessagePageRequest, NewMessage, PageDirection,
+    ParticipantRole, ReadReceipt, UserRole,
+};
+use crate::repository::Repository;
 use bcrypt::{hash, verify, DEFAULT_COST};
-use diesel::pg::PgConnection;
-use diesel::prelude::*;
-use dotenv::dotenv;
+use chrono::NaiveDateTime;
+use diesel::result::Error as DieselError;
+use diesel::OptionalExtension;
 use jsonwebtoken::{decode, errors::ErrorKind, DecodingKey, EncodingKey, Header, Validation};
-use log::info;
-use std::cmp::min;
-use std::env;
+use rand::Rng;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 
-pub fn get_user_by_id(_id: i32) -> Result<User, diesel::result::Error> {
-    let mut connection = establish_mutable_connection();
-    use crate::schema::users::dsl::*;
-    users.filter(id.eq(id)).first::<User>(&mut connection)
+pub fn validate_meeting_times(
+    start_time: NaiveDateTime,
+    end_time: NaiveDateTime,
+) -> Result<(), ApiError> {
+    if start_time >= end_time {
+        return Err(ApiError::Validation(
+            "Meeting start_time must be before end_time".to_string(),
+        ));
+    }
+    Ok(())
 }
 
-pub fn get_user_by_email(_email: &str) -> Result<User, diesel::result::Error> {
-    let mut connection = establish_mutable_connection();
-    use crate::schema::users::dsl::*;
-    users.filter(email.eq(email)).first::<User>(&mut connection)
+pub const MAX_MESSAGE_LENGTH: usize = 4000;
+
+pub fn validate_message(new_message: &NewMessage) -> Result<(), ApiError> {
+    validate_content(&new_message.content)
 }
 
-pub fn create_user(user: NewUser) -> Result<User, diesel::result::Error> {
-    let mut connection = establish_mutable_connection();
-    use crate::schema::users::dsl::*;
-    diesel::insert_into(users)
-        .values(&user)
-        .get_result::<User>(&mut connection)
+/// Checks message content, whether it is new or replaces an edited message.
+pub fn validate_content(content: &str) -> Result<(), ApiError> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(ApiError::Validation(
+            "Message content must not be empty".to_string(),
+        ));
+    }
+    if content.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(ApiError::Validation(format!(
+            "Message content must be at most {} characters",
+            MAX_MESSAGE_LENGTH
+        )));
+    }
+    Ok(())
 }
 
-pub fn create_meeting(new_meeting: NewMeeting) -> Result<Meeting, diesel::result::Error> {
-    let mut connection = establish_mutable_connection();
-    use crate::schema::meetings::dsl::*;
-    diesel::insert_into(meetings)
-        .values(&new_meeting)
-        .get_result(&mut connection)
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+/// Checks the paging parameters shared by `GET /api/messages/{meeting_id}`
+/// and the WebSocket `history` frame. Without a cursor the page ends at the
+/// newest message.
+pub fn message_page_request(
+    before: Option<&str>,
+    after: Option<&str>,
+    limit: Option<i64>,
+) -> Result<MessagePageRequest, ApiError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::Validation(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let decode = |token: &str| {
+        MessageCursor::decode(token)
+            .ok_or_else(|| ApiError::BadRequest(format!("Invalid cursor: {}", token)))
+    };
+    let (direction, cursor) = match (before, after) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::BadRequest(
+                "Use either before or after, not both".to_string(),
+            ))
+        }
+        (Some(before), None) => (PageDirection::Before, Some(decode(before)?)),
+        (None, Some(after)) => (PageDirection::After, Some(decode(after)?)),
+        (None, None) => (PageDirection::Before, None),
+    };
+    Ok(MessagePageRequest {
+        direction,
+        cursor,
+        limit,
+    })
 }
 
-pub fn get_meeting_by_id(_id: i32) -> Result<Meeting, diesel::result::Error> {
-    let mut connection = establish_mutable_connection();
-    use crate::schema::meetings::dsl::*;
-    meetings.find(id).first::<Meeting>(&mut connection)
+/// Maps a failed participant lookup to the error shown to the caller. A
+/// meeting that does not exist looks the same as one the caller is not in.
+pub fn not_a_participant(e: DbError) -> ApiError {
+    match e {
+        DbError::Query(DieselError::NotFound) => {
+            ApiError::Forbidden("You are not a participant in this meeting".to_string())
+        }
+        e => e.into(),
+    }
 }
 
-pub fn create_message(new_message: NewMessage) -> Result<Message, diesel::result::Error> {
-    let mut connection = establish_mutable_connection();
-    use crate::schema::messages::dsl::*;
-    diesel::insert_into(messages)
-        .values(&new_message)
-        .get_result(&mut connection)
+/// Moves a user's read marker in a meeting forward. Callers check that the
+/// user participates in the meeting.
+pub async fn mark_read(
+    pool: &DbPool,
+    meeting_id: i32,
+    user_id: i32,
+    message_id: i32,
+) -> Result<ReadReceipt, ApiError> {
+    let now = chrono::Utc::now().naive_utc();
+    db::run(pool, move |conn| {
+        conn.mark_read(meeting_id, user_id, message_id, now)
+    })
+    .await
+    .map_err(|e| match e {
+        DbError::Query(DieselError::NotFound) => {
+            ApiError::NotFound(format!("Message {} not found in this meeting", message_id))
+        }
+        e => e.into(),
+    })
 }
 
-pub fn get_messages_for_meeting(_meeting_id: i32) -> Result<Vec<Message>, diesel::result::Error> {
-    let mut connection = establish_mutable_connection();
-    use crate::schema::messages::dsl::*;
-    messages
-        .filter(meeting_id.eq(meeting_id))
-        .load::<Message>(&mut connection)
+pub fn ensure_can_post(role: ParticipantRole) -> Result<(), ApiError> {
+    if !role.can_post() {
+        return Err(ApiError::Forbidden(
+            "Viewers cannot post messages in this meeting".to_string(),
+        ));
+    }
+    Ok(())
 }
 
-pub fn establish_mutable_connection() -> PgConnection {
-    dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    PgConnection::establish(&database_url).expect("Error connecting to database")
+pub const DEFAULT_JOIN_LINK_HOURS: i64 = 24;
+pub const MAX_JOIN_LINK_HOURS: i64 = 24 * 30;
+
+/// Works out when a new join link expires, rejecting lifetimes outside
+/// 1 hour to `MAX_JOIN_LINK_HOURS`.
+pub fn join_link_expiry(
+    expires_in_hours: Option<i64>,
+    now: NaiveDateTime,
+) -> Result<NaiveDateTime, ApiError> {
+    let hours = expires_in_hours.unwrap_or(DEFAULT_JOIN_LINK_HOURS);
+    if !(1..=MAX_JOIN_LINK_HOURS).contains(&hours) {
+        return Err(ApiError::Validation(format!(
+            "expires_in_hours must be between 1 and {}",
+            MAX_JOIN_LINK_HOURS
+        )));
+    }
+    Ok(now + chrono::Duration::hours(hours))
 }
 
 pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
@@ -73,66 +157,105 @@ pub fn verify_password(password: &str, hash: &str) -> Result<bool, bcrypt::Bcryp
     verify(password, hash)
 }
 
-pub fn generate_jwt(user_id: &str) -> Result<String, jsonwebtoken::errors::Error> {
+/// Signs any claims with the server's HS256 secret.
+fn sign_token<T: Serialize>(auth: &AuthConfig, claims: &T) -> Result<String, ApiError> {
+    let encoding_key = EncodingKey::from_secret(auth.jwt_secret.as_ref());
+    jsonwebtoken::encode(&Header::default(), claims, &encoding_key)
+        .map_err(|e| ApiError::Internal(format!("Error generating token: {}", e)))
+}
+
+/// Access tokens are short-lived; clients renew them with a refresh token.
+pub fn generate_jwt(
+    auth: &AuthConfig,
+    user_id: i32,
+    session_id: i32,
+    role: UserRole,
+) -> Result<String, ApiError> {
     let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::hours(24))
+        .checked_add_signed(auth.access_token_lifetime())
         .expect("valid timestamp")
         .timestamp();
 
     let claims = Claims {
-        sub: user_id.to_owned(),
+        sub: user_id.to_string(),
+        sid: session_id,
+        role,
         exp: expiration as usize,
     };
 
-    let encoding_key = EncodingKey::from_secret(
-        env::var("JWT_SECRET")
-            .expect("JWT_SECRET must be set")
-            .as_ref(),
-    );
+    let token = sign_token(auth, &claims)?;
+    tracing::debug!(user_id, session_id, "access token issued");
+    Ok(token)
+}
 
-    match jsonwebtoken::encode(&Header::default(), &claims, &encoding_key) {
-        Ok(token) => {
-            info!("Token generated successfully: {}", token);
-            Ok(token)
-        }
-        Err(err) => {
-            info!("Error generating token: {}", err);
-            Err(err)
-        }
-    }
+/// A random opaque refresh token. Only its hash is stored.
+pub fn generate_refresh_token() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    hex(&bytes)
 }
 
-pub fn decode_jwt(token: &str) -> Result<Claims, actix_web::Error> {
-    log::info!("Decoding JWT token: {}", &token[0..min(10, token.len())]);
+pub fn hash_refresh_token(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
 
-    let jwt_secret = match env::var("JWT_SECRET") {
-        Ok(secret) => secret,
-        Err(_) => {
-            log::error!("JWT_SECRET environment variable is not set.");
-            return Err(ErrorUnauthorized("JWT secret is not configured").into());
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Signs the token behind a join link. It expires together with the link,
+/// so the same link always yields the same token.
+pub fn generate_join_token(auth: &AuthConfig, link: &JoinLink) -> Result<String, ApiError> {
+    sign_token(
+        auth,
+        &JoinLinkClaims {
+            link_id: link.id,
+            meeting_id: link.meeting_id,
+            exp: link.expires_at.and_utc().timestamp() as usize,
+        },
+    )
+}
+
+pub fn decode_join_token(auth: &AuthConfig, token: &str) -> Result<JoinLinkClaims, ApiError> {
+    let decoding_key = DecodingKey::from_secret(auth.jwt_secret.as_ref());
+    decode::<JoinLinkClaims>(token, &decoding_key, &Validation::default())
+        .map(|token_data| token_data.claims)
+        .map_err(|err| {
+            tracing::info!(error = %err, "rejected join token");
+            ApiError::NotFound("Join link is invalid or has expired".to_string())
+        })
+}
+
+/// Verifies an access token and checks that its session is still live.
+pub async fn decode_jwt(pool: &DbPool, auth: &AuthConfig, token: &str) -> Result<Claims, ApiError> {
+    let decoding_key = DecodingKey::from_secret(auth.jwt_secret.as_ref());
+
+    let claims = match decode::<Claims>(token, &decoding_key, &Validation::default()) {
+        Ok(token_data) => token_data.claims,
+        Err(err) => {
+            tracing::info!(error = %err, "rejected access token");
+            return match *err.kind() {
+                ErrorKind::ExpiredSignature => {
+                    Err(ApiError::Unauthorized("Token has expired".to_string()))
+                }
+                ErrorKind::InvalidToken => Err(ApiError::Unauthorized("Invalid token".to_string())),
+                _ => Err(ApiError::Unauthorized("Error decoding token".to_string())),
+            };
         }
     };
 
-    let decoding_key = DecodingKey::from_secret(jwt_secret.as_ref());
-
-    match decode::<Claims>(token, &decoding_key, &Validation::default()) {
-        Ok(token_data) => {
-            log::info!("Token successfully decoded: {:?}", token_data.claims);
-            Ok(token_data.claims)
+    let session_id = claims.sid;
+    let session = db::run(pool, move |conn| conn.get_session(session_id).optional()).await?;
+    match session {
+        Some(session)
+            if session.revoked_at.is_none() && session.user_id.to_string() == claims.sub =>
+        {
+            Ok(claims)
+        }
+        _ => {
+            tracing::info!(session_id, "rejected token for revoked session");
+            Err(ApiError::Unauthorized(
+                "Session has been revoked".to_string(),
+            ))
         }
-        Err(err) => match *err.kind() {
-            ErrorKind::ExpiredSignature => {
-                log::error!("Token has expired: {:?}", err);
-                Err(ErrorUnauthorized("Token has expired").into())
-            }
-            ErrorKind::InvalidToken => {
-                log::error!("Invalid token: {:?}", err);
-                Err(ErrorUnauthorized("Invalid token").into())
-            }
-            _ => {
-                log::error!("Error decoding token: {:?}", err);
-                Err(ErrorUnauthorized("Error decoding token").into())
-            }
-        },
     }
 }
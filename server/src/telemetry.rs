@@ -0,0 +1,409 @@
+use crate::config::{LogConfig, LogFormat};
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{Error, HttpMessage};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Instrument, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+pub const REDACTED: &str = "[redacted]";
+
+/// Fields whose name contains one of these never have their value logged.
+const SENSITIVE_FIELDS: &[&str] = &[
+    "password",
+    "passcode",
+    "token",
+    "secret",
+    "authorization",
+    "cookie",
+    "email",
+];
+
+pub fn is_sensitive(field: &str) -> bool {
+    let field = field.to_ascii_lowercase();
+    SENSITIVE_FIELDS.iter().any(|name| field.contains(name))
+}
+
+fn is_secret_word(word: &str) -> bool {
+    let looks_like_jwt = word.starts_with("eyJ")
+        && word.split('.').count() == 3
+        && word.split('.').all(|part| !part.is_empty());
+    let looks_like_email = word.split_once('@').is_some_and(|(local, domain)| {
+        !local.is_empty() && domain.contains('.') && !domain.starts_with('.')
+    });
+    // Refresh tokens and their hashes are long hex strings.
+    let looks_like_hex_token = word.len() >= 32 && word.chars().all(|c| c.is_ascii_hexdigit());
+    looks_like_jwt || looks_like_email || looks_like_hex_token
+}
+
+/// Masks JWTs, email addresses and hex tokens in free text, such as a log
+/// message or a `Debug` dump that slipped through.
+pub fn redact_text(text: &str) -> Cow<'_, str> {
+    let is_word_char = |c: char| c.is_ascii_alphanumeric() || "._%+-@".contains(c);
+
+    let mut redacted = String::new();
+    let mut copied = 0;
+    let mut word_start = None;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        if is_word_char(c) {
+            word_start.get_or_insert(i);
+            continue;
+        }
+        if let Some(start) = word_start.take() {
+            if is_secret_word(&text[start..i]) {
+                redacted.push_str(&text[copied..start]);
+                redacted.push_str(REDACTED);
+                copied = i;
+            }
+        }
+    }
+
+    if copied == 0 {
+        Cow::Borrowed(text)
+    } else {
+        redacted.push_str(&text[copied..]);
+        Cow::Owned(redacted)
+    }
+}
+
+/// Collects fields into a JSON object, redacting as it goes.
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl JsonVisitor<'_> {
+    fn insert(&mut self, field: &Field, value: Value) {
+        let value = if is_sensitive(field.name()) {
+            Value::from(REDACTED)
+        } else {
+            value
+        };
+        self.0.insert(field.name().to_string(), value);
+    }
+}
+
+impl Visit for JsonVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, redact_text(value).into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, redact_text(&format!("{:?}", value)).into());
+    }
+}
+
+/// Writes `name=value` pairs for the text format, redacting as it goes.
+struct TextVisitor<'a, 'w> {
+    writer: &'a mut Writer<'w>,
+    result: fmt::Result,
+    empty: bool,
+}
+
+impl TextVisitor<'_, '_> {
+    fn write(&mut self, field: &Field, value: &str) {
+        if self.result.is_err() {
+            return;
+        }
+        let separator = if self.empty { "" } else { " " };
+        self.empty = false;
+        self.result = if field.name() == "message" {
+            write!(self.writer, "{}{}", separator, redact_text(value))
+        } else if is_sensitive(field.name()) {
+            write!(self.writer, "{}{}={}", separator, field.name(), REDACTED)
+        } else {
+            write!(
+                self.writer,
+                "{}{}={}",
+                separator,
+                field.name(),
+                redact_text(value)
+            )
+        };
+    }
+}
+
+impl Visit for TextVisitor<'_, '_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.write(field, &format!("{:?}", value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.write(field, &format!("{:?}", value));
+    }
+}
+
+/// Field formatter for the text format.
+pub struct RedactingFields;
+
+impl<'writer> FormatFields<'writer> for RedactingFields {
+    fn format_fields<R: tracing_subscriber::field::RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut visitor = TextVisitor {
+            writer: &mut writer,
+            result: Ok(()),
+            empty: true,
+        };
+        fields.record(&mut visitor);
+        visitor.result
+    }
+}
+
+/// Field formatter for the JSON format. Span fields are kept as a JSON
+/// object so `JsonFormat` can merge them into each event.
+pub struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: tracing_subscriber::field::RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut map = Map::new();
+        fields.record(&mut JsonVisitor(&mut map));
+        write!(writer, "{}", Value::Object(map))
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let mut map = match serde_json::from_str(&current.fields) {
+            Ok(Value::Object(map)) => map,
+            _ => Map::new(),
+        };
+        fields.record(&mut JsonVisitor(&mut map));
+        current.fields = Value::Object(map).to_string();
+        Ok(())
+    }
+}
+
+/// One JSON object per line: timestamp, level, target, the fields of every
+/// enclosing span (such as `request_id`) and the event's own fields.
+pub struct JsonFormat;
+
+impl<S> FormatEvent<S, JsonFields> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".to_string(),
+            chrono::Utc::now().to_rfc3339().into(),
+        );
+        line.insert("level".to_string(), metadata.level().as_str().into());
+        line.insert("target".to_string(), metadata.target().into());
+
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                line.insert("span".to_string(), span.name().into());
+                let extensions = span.extensions();
+                let Some(fields) = extensions.get::<FormattedFields<JsonFields>>() else {
+                    continue;
+                };
+                if let Ok(Value::Object(fields)) = serde_json::from_str(&fields.fields) {
+                    line.extend(fields);
+                }
+            }
+        }
+        event.record(&mut JsonVisitor(&mut line));
+
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+/// Installs the global subscriber. Records from crates that still use the
+/// `log` macros are forwarded to it as well.
+pub fn init(config: &LogConfig) {
+    let filter = EnvFilter::try_new(&config.level).unwrap_or_else(|_| EnvFilter::new("info"));
+    let registry = tracing_subscriber::registry().with(filter);
+    match config.format {
+        LogFormat::Text => registry
+            .with(tracing_subscriber::fmt::layer().fmt_fields(RedactingFields))
+            .init(),
+        LogFormat::Json => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .fmt_fields(JsonFields)
+                    .event_format(JsonFormat),
+            )
+            .init(),
+    }
+}
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// The id of the request being served, from its `X-Request-Id` header or
+/// generated. Stored in the request extensions.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Accepts a caller's id only if it is short and printable, so it cannot be
+/// used to inject text into the logs.
+fn incoming_request_id(req: &ServiceRequest) -> Option<String> {
+    let id = req.headers().get(&REQUEST_ID_HEADER)?.to_str().ok()?;
+    let valid = !id.is_empty() && id.len() <= 128 && id.chars().all(|c| c.is_ascii_graphic());
+    valid.then(|| id.to_string())
+}
+
+/// Runs each request inside a `request` span carrying its id, method and
+/// route, logs one line when it completes and echoes the id back in the
+/// `X-Request-Id` response header. Wrapped outermost so every other log
+/// line of the request, including authentication failures, carries the id.
+pub struct RequestTracing;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestTracing
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestTracingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestTracingMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+pub struct RequestTracingMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestTracingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let svc = Rc::clone(&self.service);
+        let request_id =
+            incoming_request_id(&req).unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        req.extensions_mut().insert(RequestId(request_id.clone()));
+        let span = tracing::info_span!(
+            "request",
+            request_id = %request_id,
+            method = %req.method(),
+            route = %req.match_pattern().unwrap_or_else(|| "unmatched".to_string()),
+        );
+
+        Box::pin(
+            async move {
+                let started = Instant::now();
+                let mut res = match svc.call(req).await {
+                    Ok(res) => res,
+                    Err(e) => {
+                        let status = e.as_response_error().status_code().as_u16();
+                        tracing::error!(status, error = %e, "request failed");
+                        return Err(e);
+                    }
+                };
+
+                let status = res.status().as_u16();
+                let latency_ms = started.elapsed().as_millis() as u64;
+                if res.status().is_server_error() {
+                    tracing::error!(status, latency_ms, "request failed");
+                } else {
+                    tracing::info!(status, latency_ms, "request completed");
+                }
+
+                if let Ok(value) = HeaderValue::from_str(&request_id) {
+                    res.headers_mut().insert(REQUEST_ID_HEADER, value);
+                }
+                Ok(res)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_tokens_and_emails_in_text() {
+        assert_eq!(
+            redact_text("login for alice@example.com failed"),
+            "login for [redacted] failed"
+        );
+        assert_eq!(
+            redact_text("Bearer eyJhbGciOi.eyJzdWIiOiIxIn0.c2lnbmF0dXJl, rest"),
+            "Bearer [redacted], rest"
+        );
+        assert_eq!(
+            redact_text(&format!("refresh_token: \"{}\"", "ab12".repeat(16))),
+            "refresh_token: \"[redacted]\""
+        );
+        assert!(matches!(
+            redact_text("meeting 12 at 10:00"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn sensitive_field_names() {
+        for name in [
+            "password",
+            "refresh_token",
+            "invitee_email",
+            "passcode_hash",
+        ] {
+            assert!(is_sensitive(name), "{}", name);
+        }
+        for name in ["user_id", "meeting_id", "status", "message"] {
+            assert!(!is_sensitive(name), "{}", name);
+        }
+    }
+}
@@ -1,37 +1,848 @@
-use actix::{Actor, StreamHandler};
+use crate::config::{Config, WebSocketConfig};
+use crate::db::{self, DbPool};
+use crate::errors::ApiError;
+use crate::metrics::Metrics;
+use crate::models::{Claims, Message, NewMessage, ReadReceipt};
+use crate::repository::Repository;
+use crate::room::{
+    Broadcast, CloseRoom, CloseSession, Connect, Disconnect, Evict, Evicted, Join, Leave, Publish,
+    Revoke, RoomFrame, RoomRegistry, RoomSnapshot, SendTo, SetMedia,
+};
+use crate::services::{
+    ensure_can_post, mark_read, message_page_request, not_a_participant, validate_message,
+};
+use actix::{
+    fut, Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, ContextFutureSpawner, Handler,
+    SpawnHandle, StreamHandler, WrapFuture,
+};
+use actix_web::http::header;
 use actix_web::{web, HttpRequest, HttpResponse, Result};
 use actix_web_actors::ws::{self, WebsocketContext};
+use protocol::{
+    ClientEnvelope, ClientFrame, ErrorCode, LeaveReason, Peer, ServerFrame, Signal,
+    PROTOCOL_VERSION, SUPPORTED_VERSIONS,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Instant;
 
-pub async fn websocket_handler(req: HttpRequest, stream: web::Payload) -> Result<HttpResponse, actix_web::Error> {
-    ws::start(WsSession::new(), &req, stream).map_err(|e| {
-        log::error!("WebSocket handshake failed: {:?}", e);
-        e
-    })
+#[derive(Debug, Deserialize)]
+pub struct WsQuery {
+    pub meeting_id: Option<i32>,
 }
 
-pub struct WsSession;
+impl From<&ApiError> for ErrorCode {
+    fn from(e: &ApiError) -> Self {
+        match e {
+            ApiError::BadRequest(_) => ErrorCode::BadRequest,
+            ApiError::Unauthorized(_) => ErrorCode::Unauthorized,
+            ApiError::Forbidden(_) | ApiError::MissingPermission { .. } => ErrorCode::Forbidden,
+            ApiError::NotFound(_) => ErrorCode::NotFound,
+            ApiError::Conflict(_) => ErrorCode::Conflict,
+            ApiError::Validation(_) => ErrorCode::ValidationError,
+            ApiError::ServiceUnavailable(_) => ErrorCode::ServiceUnavailable,
+            ApiError::Internal(_) => ErrorCode::InternalError,
+        }
+    }
+}
+
+/// The protocol version to speak, from the `convo.v<N>` subprotocols the
+/// client offered. Clients that offer none get the current version.
+fn negotiate_version(req: &HttpRequest) -> Result<Option<u16>, ApiError> {
+    let Some(offered) = req.headers().get(header::SEC_WEBSOCKET_PROTOCOL) else {
+        return Ok(None);
+    };
+    let offered = offered.to_str().unwrap_or_default();
+    protocol::negotiate(offered.split(','))
+        .map(Some)
+        .ok_or_else(|| {
+            let supported: Vec<String> = SUPPORTED_VERSIONS
+                .iter()
+                .map(|version| protocol::subprotocol(*version))
+                .collect();
+            ApiError::BadRequest(format!(
+                "Unsupported protocol `{}`; this server speaks {}",
+                offered,
+                supported.join(", ")
+            ))
+        })
+}
+
+/// The `id` of a frame that failed to parse, so the error can still be
+/// matched to it.
+fn frame_id(text: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(text).ok()?;
+    value.get("id")?.as_str().map(str::to_string)
+}
+
+/// Pushes a stored message to every live session in its meeting.
+pub fn publish_message(rooms: &Addr<RoomRegistry>, message: &Message) {
+    rooms.do_send(Publish {
+        meeting_id: message.meeting_id,
+        frame: RoomFrame(
+            ServerFrame::Chat {
+                message: message.clone().into(),
+            }
+            .to_text(),
+        ),
+    });
+}
+
+/// Pushes an edited message, or the tombstone of a deleted one, to every
+/// session in its meeting.
+pub fn publish_message_change(rooms: &Addr<RoomRegistry>, message: &Message) {
+    let frame = if message.deleted_at.is_some() {
+        ServerFrame::ChatDeleted {
+            message: message.clone().into(),
+        }
+    } else {
+        ServerFrame::ChatEdited {
+            message: message.clone().into(),
+        }
+    };
+    rooms.do_send(Publish {
+        meeting_id: message.meeting_id,
+        frame: RoomFrame(frame.to_text()),
+    });
+}
+
+/// Pushes a participant's new read marker to every session in the meeting.
+pub fn publish_read(rooms: &Addr<RoomRegistry>, receipt: &ReadReceipt) {
+    rooms.do_send(Publish {
+        meeting_id: receipt.meeting_id,
+        frame: RoomFrame(
+            ServerFrame::Read {
+                meeting_id: receipt.meeting_id,
+                user_id: receipt.user_id,
+                message_id: receipt.last_read_message_id,
+            }
+            .to_text(),
+        ),
+    });
+}
+
+/// Closes the live sessions opened with revoked login sessions, see `Revoke`.
+pub fn close_revoked_sessions(
+    rooms: &Addr<RoomRegistry>,
+    user_id: i32,
+    auth_session_id: Option<i32>,
+) {
+    rooms.do_send(Revoke {
+        user_id,
+        auth_session_id,
+    });
+}
+
+/// Drops every live session of a removed participant out of the meeting room.
+pub fn evict_participant(rooms: &Addr<RoomRegistry>, meeting_id: i32, user_id: i32) {
+    rooms.do_send(Evict {
+        meeting_id,
+        user_id,
+    });
+}
+
+/// Drops every live session out of a deleted meeting's room.
+pub fn close_meeting_room(rooms: &Addr<RoomRegistry>, meeting_id: i32) {
+    rooms.do_send(CloseRoom { meeting_id });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn websocket_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    query: web::Query<WsQuery>,
+    rooms: web::Data<Addr<RoomRegistry>>,
+    pool: web::Data<DbPool>,
+    metrics: web::Data<Metrics>,
+    config: web::Data<Config>,
+    claims: Claims,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = claims.user_id()?;
+    let negotiated = negotiate_version(&req)?;
+
+    let session = WsSession::new(
+        rooms.get_ref().clone(),
+        pool.get_ref().clone(),
+        metrics.into_inner(),
+        config.websocket.clone(),
+        negotiated.unwrap_or(PROTOCOL_VERSION),
+        user_id,
+        claims.sid,
+        query.meeting_id,
+    );
+    let subprotocol = negotiated.map(protocol::subprotocol);
+    let subprotocols: Vec<&str> = subprotocol.as_deref().into_iter().collect();
+    ws::WsResponseBuilder::new(session, &req, stream)
+        .protocols(&subprotocols)
+        .start()
+        .map_err(|e| {
+            tracing::error!(error = %e, "websocket handshake failed");
+            e
+        })
+}
+
+pub struct WsSession {
+    id: usize,
+    /// The negotiated protocol version.
+    version: u16,
+    user_id: i32,
+    /// The login session of the access token the session opened with.
+    auth_session_id: i32,
+    meeting_id: Option<i32>,
+    rooms: Addr<RoomRegistry>,
+    pool: DbPool,
+    metrics: Arc<Metrics>,
+    config: WebSocketConfig,
+    span: tracing::Span,
+    /// When the client last sent any frame.
+    last_heartbeat: Instant,
+    /// Set when the session is dropped for a reason its meeting should hear.
+    leave_reason: Option<LeaveReason>,
+    /// Set while the rest of the meeting is shown that the user is typing.
+    /// Fires when the indicator expires.
+    typing: Option<SpawnHandle>,
+    /// Chat messages still being written to the database.
+    pending_writes: usize,
+    /// A close frame was exchanged; the session stops once its pending
+    /// writes are done.
+    closing: bool,
+}
 
 impl WsSession {
-    fn new() -> Self {
-        Self
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        rooms: Addr<RoomRegistry>,
+        pool: DbPool,
+        metrics: Arc<Metrics>,
+        config: WebSocketConfig,
+        version: u16,
+        user_id: i32,
+        auth_session_id: i32,
+        meeting_id: Option<i32>,
+    ) -> Self {
+        // Created inside the handshake's request span, so everything the
+        // session logs carries the request id.
+        let span = tracing::info_span!("ws", user_id, session_id = tracing::field::Empty);
+        Self {
+            id: 0,
+            version,
+            user_id,
+            auth_session_id,
+            meeting_id,
+            rooms,
+            pool,
+            metrics,
+            config,
+            span,
+            last_heartbeat: Instant::now(),
+            leave_reason: None,
+            typing: None,
+            pending_writes: 0,
+            closing: false,
+        }
+    }
+
+    fn peer(&self) -> Peer {
+        Peer {
+            session_id: self.id,
+            user_id: self.user_id,
+        }
+    }
+
+    fn send(&self, frame: ServerFrame, ctx: &mut WebsocketContext<Self>) {
+        ctx.text(frame.to_text());
+    }
+
+    /// Confirms a handled frame, if the client gave it an id.
+    fn ack(&self, id: Option<String>, ctx: &mut WebsocketContext<Self>) {
+        if let Some(id) = id {
+            self.send(ServerFrame::Ack { id }, ctx);
+        }
+    }
+
+    fn error(
+        &self,
+        id: Option<String>,
+        code: ErrorCode,
+        message: impl Into<String>,
+        ctx: &mut WebsocketContext<Self>,
+    ) {
+        self.send(ServerFrame::error(id, code, message), ctx);
+    }
+
+    fn reject(&self, id: Option<String>, e: &ApiError, ctx: &mut WebsocketContext<Self>) {
+        self.error(id, e.into(), e.message(), ctx);
+    }
+
+    /// The meeting the session is in, or an error reply if it is in none.
+    fn current_meeting(
+        &self,
+        id: &Option<String>,
+        ctx: &mut WebsocketContext<Self>,
+    ) -> Option<i32> {
+        if self.meeting_id.is_none() {
+            self.error(
+                id.clone(),
+                ErrorCode::NotInMeeting,
+                "Join a meeting first",
+                ctx,
+            );
+        }
+        self.meeting_id
+    }
+
+    fn dispatch(
+        &mut self,
+        id: Option<String>,
+        frame: ClientFrame,
+        ctx: &mut WebsocketContext<Self>,
+    ) {
+        match frame {
+            ClientFrame::Join { meeting_id } => self.join(id, meeting_id, ctx),
+            ClientFrame::Leave => {
+                self.leave(ctx);
+                self.ack(id, ctx);
+            }
+            ClientFrame::Chat { content } => self.chat(id, content, ctx),
+            ClientFrame::Typing { active } => self.typing(id, active, ctx),
+            ClientFrame::Read { message_id } => self.read(id, message_id, ctx),
+            ClientFrame::History {
+                before,
+                after,
+                limit,
+            } => self.history(id, before, after, limit, ctx),
+            ClientFrame::Media { muted, camera } => self.media(id, muted, camera, ctx),
+            ClientFrame::Signal { target, signal } => self.signal(id, target, signal, ctx),
+            ClientFrame::Relay { payload } => self.relay(id, payload, ctx),
+        }
+    }
+
+    /// Enters a meeting room once the database confirms the user is one of
+    /// its participants.
+    fn join(&mut self, id: Option<String>, meeting_id: i32, ctx: &mut WebsocketContext<Self>) {
+        self.stop_typing(ctx);
+        let pool = self.pool.clone();
+        let rooms = self.rooms.clone();
+        let (session_id, user_id) = (self.id, self.user_id);
+        async move {
+            db::run(&pool, move |conn| conn.get_participant(meeting_id, user_id))
+                .await
+                .map_err(not_a_participant)?;
+            rooms
+                .send(Join {
+                    session_id,
+                    meeting_id,
+                })
+                .await
+                .map_err(|e| ApiError::Internal(format!("room registry unavailable: {}", e)))
+        }
+        .into_actor(self)
+        .then(move |res, act, ctx| {
+            let _span = act.span.clone().entered();
+            match res {
+                Ok(RoomSnapshot { peers, roster }) => {
+                    tracing::info!(meeting_id, "joined meeting");
+                    act.meeting_id = Some(meeting_id);
+                    act.send(
+                        ServerFrame::Joined {
+                            session_id: act.id,
+                            meeting_id,
+                            peers,
+                        },
+                        ctx,
+                    );
+                    act.send(ServerFrame::Roster(roster), ctx);
+                    act.ack(id, ctx);
+                }
+                Err(e) => {
+                    tracing::warn!(meeting_id, error = %e, "join rejected");
+                    act.reject(id, &e, ctx);
+                }
+            }
+            fut::ready(())
+        })
+        .wait(ctx);
+    }
+
+    /// Sends a close frame, unless one was sent already, and stops once no
+    /// chat message is still being stored.
+    fn close(&mut self, reason: Option<ws::CloseReason>, ctx: &mut WebsocketContext<Self>) {
+        if !self.closing {
+            self.closing = true;
+            ctx.close(reason);
+        }
+        self.stop_when_idle(ctx);
+    }
+
+    fn stop_when_idle(&self, ctx: &mut WebsocketContext<Self>) {
+        if self.closing && self.pending_writes == 0 {
+            ctx.stop();
+        }
+    }
+
+    /// Pings the client every `heartbeat_interval` and drops the session
+    /// once it stayed silent for longer than `client_timeout`.
+    fn heartbeat(&self, ctx: &mut WebsocketContext<Self>) {
+        ctx.run_interval(self.config.heartbeat_interval(), |act, ctx| {
+            if act.last_heartbeat.elapsed() > act.config.client_timeout() {
+                let _span = act.span.clone().entered();
+                tracing::warn!("websocket client timed out");
+                act.leave_reason = Some(LeaveReason::Timeout);
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    fn leave(&mut self, ctx: &mut WebsocketContext<Self>) {
+        self.stop_typing(ctx);
+        if self.meeting_id.take().is_some() {
+            self.rooms.do_send(Leave {
+                session_id: self.id,
+            });
+        }
+    }
+
+    /// Passes a frame on to every other session in the meeting.
+    fn broadcast(&self, frame: ServerFrame) {
+        self.rooms.do_send(Broadcast {
+            session_id: self.id,
+            frame: RoomFrame(frame.to_text()),
+        });
+    }
+
+    fn relay(&self, id: Option<String>, payload: Value, ctx: &mut WebsocketContext<Self>) {
+        if self.current_meeting(&id, ctx).is_none() {
+            return;
+        }
+        self.broadcast(ServerFrame::Relay {
+            from: self.peer(),
+            payload,
+        });
+        self.ack(id, ctx);
+    }
+
+    fn typing(&mut self, id: Option<String>, active: bool, ctx: &mut WebsocketContext<Self>) {
+        if self.current_meeting(&id, ctx).is_none() {
+            return;
+        }
+        if active {
+            self.start_typing(ctx);
+        } else {
+            self.stop_typing(ctx);
+        }
+        self.ack(id, ctx);
+    }
+
+    /// Shows the typing indicator, or keeps it alive if it is already
+    /// shown, so clients may repeat `typing` on every keystroke.
+    fn start_typing(&mut self, ctx: &mut WebsocketContext<Self>) {
+        match self.typing.take() {
+            Some(expiry) => {
+                ctx.cancel_future(expiry);
+            }
+            None => self.broadcast_typing(true),
+        }
+        let expiry = ctx.run_later(self.config.typing_timeout(), |act, _| {
+            act.typing = None;
+            act.broadcast_typing(false);
+        });
+        self.typing = Some(expiry);
+    }
+
+    fn stop_typing(&mut self, ctx: &mut WebsocketContext<Self>) {
+        if let Some(expiry) = self.typing.take() {
+            ctx.cancel_future(expiry);
+            self.broadcast_typing(false);
+        }
+    }
+
+    fn broadcast_typing(&self, active: bool) {
+        if let Some(meeting_id) = self.meeting_id {
+            self.broadcast(ServerFrame::Typing {
+                meeting_id,
+                peer: self.peer(),
+                active,
+            });
+        }
+    }
+
+    /// Moves the user's read marker, once the database confirms they are
+    /// still a participant.
+    fn read(&self, id: Option<String>, message_id: i32, ctx: &mut WebsocketContext<Self>) {
+        let Some(meeting_id) = self.current_meeting(&id, ctx) else {
+            return;
+        };
+        let pool = self.pool.clone();
+        let user_id = self.user_id;
+        async move {
+            db::run(&pool, move |conn| conn.get_participant(meeting_id, user_id))
+                .await
+                .map_err(not_a_participant)?;
+            mark_read(&pool, meeting_id, user_id, message_id).await
+        }
+        .into_actor(self)
+        .then(move |res, act, ctx| {
+            let _span = act.span.clone().entered();
+            match res {
+                Ok(receipt) => {
+                    publish_read(&act.rooms, &receipt);
+                    act.ack(id, ctx);
+                }
+                Err(e) => {
+                    tracing::warn!(meeting_id, message_id, error = %e, "read marker rejected");
+                    act.reject(id, &e, ctx);
+                }
+            }
+            fut::ready(())
+        })
+        .spawn(ctx);
+    }
+
+    fn media(
+        &self,
+        id: Option<String>,
+        muted: Option<bool>,
+        camera: Option<bool>,
+        ctx: &mut WebsocketContext<Self>,
+    ) {
+        self.rooms
+            .send(SetMedia {
+                session_id: self.id,
+                muted,
+                camera,
+            })
+            .into_actor(self)
+            .then(|res, act, ctx| {
+                let _span = act.span.clone().entered();
+                match res {
+                    Ok(Ok(())) => act.ack(id, ctx),
+                    Ok(Err(e)) => act.error(id, ErrorCode::NotInMeeting, e.to_string(), ctx),
+                    Err(e) => {
+                        tracing::error!(error = %e, "failed to update media state");
+                        act.error(id, ErrorCode::InternalError, "Internal server error", ctx);
+                    }
+                }
+                fut::ready(())
+            })
+            .spawn(ctx);
+    }
+
+    fn signal(
+        &self,
+        id: Option<String>,
+        target: usize,
+        signal: Signal,
+        ctx: &mut WebsocketContext<Self>,
+    ) {
+        let frame = ServerFrame::Signal {
+            from: self.peer(),
+            signal,
+        };
+        self.rooms
+            .send(SendTo {
+                session_id: self.id,
+                target,
+                frame: RoomFrame(frame.to_text()),
+            })
+            .into_actor(self)
+            .then(|res, act, ctx| {
+                let _span = act.span.clone().entered();
+                match res {
+                    Ok(Ok(())) => act.ack(id, ctx),
+                    Ok(Err(e)) => {
+                        tracing::warn!(error = %e, "signal rejected");
+                        act.error(id, ErrorCode::NotInMeeting, e.to_string(), ctx);
+                    }
+                    Err(e) => {
+                        tracing::error!(error = %e, "failed to relay signal");
+                        act.error(id, ErrorCode::InternalError, "Internal server error", ctx);
+                    }
+                }
+                fut::ready(())
+            })
+            .spawn(ctx);
+    }
+
+    /// Replies with a page of stored messages. The reply comes before the
+    /// ack, so a client waiting for the ack already has the page. Like
+    /// `chat`, it checks membership again so a removed participant cannot
+    /// keep reading.
+    fn history(
+        &self,
+        id: Option<String>,
+        before: Option<String>,
+        after: Option<String>,
+        limit: Option<u32>,
+        ctx: &mut WebsocketContext<Self>,
+    ) {
+        let Some(meeting_id) = self.current_meeting(&id, ctx) else {
+            return;
+        };
+        let page =
+            match message_page_request(before.as_deref(), after.as_deref(), limit.map(i64::from)) {
+                Ok(page) => page,
+                Err(e) => {
+                    self.reject(id, &e, ctx);
+                    return;
+                }
+            };
+        let pool = self.pool.clone();
+        let user_id = self.user_id;
+        async move {
+            db::run(&pool, move |conn| conn.get_participant(meeting_id, user_id))
+                .await
+                .map_err(not_a_participant)?;
+            Ok::<_, ApiError>(
+                db::run(&pool, move |conn| conn.get_message_page(meeting_id, &page)).await?,
+            )
+        }
+        .into_actor(self)
+        .then(move |res, act, ctx| {
+            let _span = act.span.clone().entered();
+            match res {
+                Ok(page) => {
+                    act.send(
+                        ServerFrame::History {
+                            meeting_id,
+                            messages: page.messages.into_iter().map(Into::into).collect(),
+                            next_cursor: page.next_cursor,
+                        },
+                        ctx,
+                    );
+                    act.ack(id, ctx);
+                }
+                Err(e) => {
+                    tracing::warn!(meeting_id, error = %e, "history request failed");
+                    act.reject(id, &e, ctx);
+                }
+            }
+            fut::ready(())
+        })
+        .spawn(ctx);
+    }
+
+    fn chat(&mut self, id: Option<String>, content: String, ctx: &mut WebsocketContext<Self>) {
+        let Some(meeting_id) = self.current_meeting(&id, ctx) else {
+            return;
+        };
+
+        let new_message = NewMessage {
+            content,
+            sender_id: self.user_id,
+            meeting_id,
+            timestamp: chrono::Utc::now().naive_utc(),
+        };
+        if let Err(e) = validate_message(&new_message) {
+            self.reject(id, &e, ctx);
+            return;
+        }
+        self.stop_typing(ctx);
+
+        // Membership is checked again on every message so a participant who
+        // was removed or demoted mid-session can no longer post.
+        let pool = self.pool.clone();
+        let user_id = self.user_id;
+        self.pending_writes += 1;
+        async move {
+            let participant = db::run(&pool, move |conn| conn.get_participant(meeting_id, user_id))
+                .await
+                .map_err(not_a_participant)?;
+            ensure_can_post(participant.role)?;
+            Ok::<_, ApiError>(db::run(&pool, move |conn| conn.create_message(new_message)).await?)
+        }
+        .into_actor(self)
+        .then(move |res, act, ctx| {
+            let _span = act.span.clone().entered();
+            act.pending_writes -= 1;
+            match res {
+                Ok(message) => {
+                    act.metrics.message_stored("ws");
+                    publish_message(&act.rooms, &message);
+                    act.ack(id, ctx);
+                }
+                Err(e) => {
+                    tracing::warn!(meeting_id, error = %e, "chat message rejected");
+                    act.reject(id, &e, ctx);
+                }
+            }
+            act.stop_when_idle(ctx);
+            fut::ready(())
+        })
+        .spawn(ctx);
     }
 }
 
 impl Actor for WsSession {
     type Context = WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.heartbeat(ctx);
+        let addr = ctx.address();
+        self.rooms
+            .send(Connect {
+                addr: addr.clone().recipient(),
+                close: addr.clone().recipient(),
+                evicted: addr.recipient(),
+                user_id: self.user_id,
+                auth_session_id: self.auth_session_id,
+            })
+            .into_actor(self)
+            .then(|res, act, ctx| {
+                match res {
+                    Ok(id) => {
+                        act.id = id;
+                        act.span.record("session_id", id);
+                        let _span = act.span.clone().entered();
+                        tracing::info!(version = act.version, "websocket session connected");
+                        act.send(
+                            ServerFrame::Hello {
+                                version: act.version,
+                                session_id: id,
+                            },
+                            ctx,
+                        );
+                        // The meeting from the query string only counts once
+                        // the membership check in `join` has passed.
+                        if let Some(meeting_id) = act.meeting_id.take() {
+                            act.join(None, meeting_id, ctx);
+                        }
+                    }
+                    Err(e) => {
+                        let _span = act.span.clone().entered();
+                        tracing::error!(error = %e, "failed to register websocket session");
+                        ctx.stop();
+                    }
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> actix::Running {
+        let _span = self.span.clone().entered();
+        tracing::info!("websocket session closed");
+        if self.typing.take().is_some() {
+            self.broadcast_typing(false);
+        }
+        self.rooms.do_send(Disconnect {
+            session_id: self.id,
+            reason: self.leave_reason.unwrap_or(LeaveReason::Left),
+        });
+        actix::Running::Stop
+    }
+}
+
+impl Handler<RoomFrame> for WsSession {
+    type Result = ();
+
+    fn handle(&mut self, frame: RoomFrame, ctx: &mut Self::Context) {
+        ctx.text(frame.0);
+    }
+}
+
+/// The registry already took the session out of the room, so its typing
+/// indicator is cleared through `Publish` rather than `Broadcast`.
+impl Handler<Evicted> for WsSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: Evicted, ctx: &mut Self::Context) {
+        if self.meeting_id != Some(msg.meeting_id) {
+            return;
+        }
+        let _span = self.span.clone().entered();
+        tracing::info!(meeting_id = msg.meeting_id, "removed from meeting");
+        if let Some(expiry) = self.typing.take() {
+            ctx.cancel_future(expiry);
+            self.rooms.do_send(Publish {
+                meeting_id: msg.meeting_id,
+                frame: RoomFrame(
+                    ServerFrame::Typing {
+                        meeting_id: msg.meeting_id,
+                        peer: self.peer(),
+                        active: false,
+                    }
+                    .to_text(),
+                ),
+            });
+        }
+        self.meeting_id = None;
+        self.send(
+            ServerFrame::Removed {
+                meeting_id: msg.meeting_id,
+            },
+            ctx,
+        );
+    }
+}
+
+/// Close code 1012 (service restart) tells clients to reconnect, possibly
+/// to another instance, and join their meeting again. 1008 (policy) means
+/// the login session was revoked and the client has to log in again.
+impl Handler<CloseSession> for WsSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseSession, ctx: &mut Self::Context) {
+        let _span = self.span.clone().entered();
+        tracing::info!(reason = %msg.reason, "closing websocket session");
+        self.close(
+            Some(ws::CloseReason {
+                code: msg.code,
+                description: Some(msg.reason),
+            }),
+            ctx,
+        );
+    }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let _span = self.span.clone().entered();
+        if msg.is_ok() {
+            self.last_heartbeat = Instant::now();
+        }
         match msg {
+            // Nothing new is accepted once the close handshake started.
+            Ok(ws::Message::Text(_) | ws::Message::Binary(_)) if self.closing => (),
             Ok(ws::Message::Ping(ping)) => ctx.pong(&ping),
             Ok(ws::Message::Pong(_)) => (),
-            Ok(ws::Message::Text(text)) => ctx.text(text),
-            Ok(ws::Message::Binary(bin)) => ctx.binary(bin),
-            Ok(ws::Message::Close(reason)) => ctx.close(reason),
-            Ok(ws::Message::Continuation(_)) => (), 
-            Ok(ws::Message::Nop) => (), 
-            Err(_) => (),
+            Ok(ws::Message::Text(text)) => match ClientEnvelope::from_text(&text) {
+                Ok(ClientEnvelope { id, frame }) => self.dispatch(id, frame, ctx),
+                Err(e) => {
+                    tracing::debug!(error = %e, "malformed frame");
+                    self.error(
+                        frame_id(&text),
+                        ErrorCode::MalformedFrame,
+                        format!("Malformed frame: {}", e),
+                        ctx,
+                    );
+                }
+            },
+            Ok(ws::Message::Binary(_)) => self.error(
+                None,
+                ErrorCode::UnsupportedFrame,
+                "Binary frames are not supported; send JSON text frames",
+                ctx,
+            ),
+            Ok(ws::Message::Close(reason)) => self.close(reason, ctx),
+            Ok(ws::Message::Continuation(_)) => (),
+            Ok(ws::Message::Nop) => (),
+            Err(e) => {
+                tracing::error!(error = %e, "websocket protocol error");
+                self.leave_reason.get_or_insert(LeaveReason::Disconnected);
+                ctx.stop();
+            }
+        }
+    }
+
+    /// The connection ended without a close handshake, so the client may
+    /// still come back.
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        if !self.closing {
+            self.leave_reason.get_or_insert(LeaveReason::Disconnected);
         }
+        ctx.stop();
     }
 }
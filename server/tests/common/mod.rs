@@ -0,0 +1,28 @@
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use server::db::run_migrations;
+use std::env;
+use std::sync::Once;
+
+static MIGRATE: Once = Once::new();
+
+/// Opens a connection to the throwaway database named by
+/// `TEST_DATABASE_URL` inside a transaction that is never committed, so each
+/// test starts from the same state. The tests using it are `#[ignore]`d and
+/// run with `cargo test -- --ignored`.
+pub fn test_connection() -> PgConnection {
+    let database_url = env::var("TEST_DATABASE_URL")
+        .expect("TEST_DATABASE_URL must name a throwaway database to run database tests");
+
+    MIGRATE.call_once(|| {
+        let mut conn = PgConnection::establish(&database_url)
+            .expect("TEST_DATABASE_URL should point at a reachable database");
+        run_migrations(&mut conn).expect("failed to migrate the test database");
+    });
+
+    let mut conn = PgConnection::establish(&database_url)
+        .expect("TEST_DATABASE_URL should point at a reachable database");
+    conn.begin_test_transaction()
+        .expect("failed to begin test transaction");
+    conn
+}
@@ -0,0 +1,674 @@
+mod common;
+
+use chrono::NaiveDate;
+use common::test_connection;
+use diesel::pg::PgConnection;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use server::models::{
+    InvitationStatus, Meeting, MeetingFilter, MeetingTimeframe, Message, MessageCursor,
+    MessagePage, MessagePageRequest, NewInvitation, NewJoinLink, NewMeeting, NewMessage,
+    NewParticipant, NewUser, PageDirection, ParticipantRole, RefreshOutcome, RevisionAction,
+    UpdateMeeting, User, UserRole,
+};
+use server::repository::Repository;
+
+fn user(conn: &mut PgConnection, name: &str) -> User {
+    conn.create_user(NewUser {
+        name: name.to_string(),
+        email: format!("{}@example.com", name),
+        password: "hashed".to_string(),
+    })
+    .expect("create user")
+}
+
+fn meeting(conn: &mut PgConnection, title: &str, creator_id: i32) -> Meeting {
+    meeting_on(conn, title, creator_id, 7)
+}
+
+fn meeting_on(conn: &mut PgConnection, title: &str, creator_id: i32, day: u32) -> Meeting {
+    let day = NaiveDate::from_ymd_opt(2024, 9, day).unwrap();
+    conn.create_meeting(NewMeeting {
+        title: title.to_string(),
+        description: None,
+        start_time: day.and_hms_opt(10, 0, 0).unwrap(),
+        end_time: day.and_hms_opt(11, 0, 0).unwrap(),
+        creator_id,
+    })
+    .expect("create meeting")
+}
+
+fn message(conn: &mut PgConnection, content: &str, sender_id: i32, meeting_id: i32) -> Message {
+    conn.create_message(NewMessage {
+        content: content.to_string(),
+        sender_id,
+        meeting_id,
+        timestamp: chrono::Utc::now().naive_utc(),
+    })
+    .expect("create message")
+}
+
+fn page(direction: PageDirection, cursor: Option<&str>, limit: i64) -> MessagePageRequest {
+    MessagePageRequest {
+        direction,
+        cursor: cursor.map(|token| MessageCursor::decode(token).expect("valid cursor")),
+        limit,
+    }
+}
+
+fn newest_messages(conn: &mut PgConnection, meeting_id: i32) -> Vec<Message> {
+    conn.get_message_page(meeting_id, &page(PageDirection::Before, None, 200))
+        .expect("load messages")
+        .messages
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn get_user_by_id_returns_the_requested_user() {
+    let mut conn = test_connection();
+    let _alice = user(&mut conn, "alice");
+    let bob = user(&mut conn, "bob");
+
+    let found = conn.get_user_by_id(bob.id).unwrap();
+    assert_eq!(found.id, bob.id);
+    assert_eq!(found.email, "bob@example.com");
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn get_user_by_id_reports_missing_users() {
+    let mut conn = test_connection();
+    user(&mut conn, "alice");
+
+    assert!(matches!(
+        conn.get_user_by_id(-1),
+        Err(DieselError::NotFound)
+    ));
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn users_start_as_members_and_can_change_role() {
+    let mut conn = test_connection();
+    let alice = user(&mut conn, "alice");
+    assert_eq!(alice.role, UserRole::Member);
+
+    let updated = conn.set_user_role(alice.id, UserRole::Guest).unwrap();
+    assert_eq!(updated.role, UserRole::Guest);
+    assert_eq!(conn.get_user_by_id(alice.id).unwrap().role, UserRole::Guest);
+    assert!(matches!(
+        conn.set_user_role(-1, UserRole::Admin),
+        Err(DieselError::NotFound)
+    ));
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn get_user_by_email_matches_the_given_email() {
+    let mut conn = test_connection();
+    let _alice = user(&mut conn, "alice");
+    let bob = user(&mut conn, "bob");
+
+    let found = conn.get_user_by_email("bob@example.com").unwrap();
+    assert_eq!(found.id, bob.id);
+    assert!(matches!(
+        conn.get_user_by_email("nobody@example.com"),
+        Err(DieselError::NotFound)
+    ));
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn get_meeting_by_id_returns_the_requested_meeting() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let _standup = meeting(&mut conn, "Standup", host.id);
+    let retro = meeting(&mut conn, "Retro", host.id);
+
+    let found = conn.get_meeting_by_id(retro.id).unwrap();
+    assert_eq!(found.id, retro.id);
+    assert_eq!(found.title, "Retro");
+    assert!(matches!(
+        conn.get_meeting_by_id(-1),
+        Err(DieselError::NotFound)
+    ));
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn message_pages_only_return_that_meeting() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let standup = meeting(&mut conn, "Standup", host.id);
+    let retro = meeting(&mut conn, "Retro", host.id);
+    message(&mut conn, "first", host.id, standup.id);
+    message(&mut conn, "elsewhere", host.id, retro.id);
+    message(&mut conn, "second", host.id, standup.id);
+
+    let messages = newest_messages(&mut conn, standup.id);
+    let contents: Vec<_> = messages.iter().map(|m| m.content.as_str()).collect();
+    assert_eq!(contents, ["first", "second"]);
+    assert!(messages.iter().all(|m| m.meeting_id == standup.id));
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn read_markers_only_move_forward_and_drive_unread_counts() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let guest = user(&mut conn, "guest");
+    let standup = meeting(&mut conn, "Standup", host.id);
+    let retro = meeting(&mut conn, "Retro", host.id);
+    let other = meeting(&mut conn, "Other", guest.id);
+    let first = message(&mut conn, "first", guest.id, standup.id);
+    let second = message(&mut conn, "second", guest.id, standup.id);
+    message(&mut conn, "mine", host.id, standup.id);
+    let elsewhere = message(&mut conn, "elsewhere", guest.id, retro.id);
+    let now = chrono::Utc::now().naive_utc();
+
+    let ids = [standup.id, retro.id, other.id];
+    let unread = conn.unread_counts(host.id, &ids).unwrap();
+    assert_eq!(unread.get(&standup.id), Some(&2));
+    assert_eq!(unread.get(&retro.id), Some(&1));
+    assert_eq!(unread.get(&other.id), None);
+
+    let receipt = conn.mark_read(standup.id, host.id, second.id, now).unwrap();
+    assert_eq!(receipt.last_read_message_id, second.id);
+    let receipt = conn.mark_read(standup.id, host.id, first.id, now).unwrap();
+    assert_eq!(receipt.last_read_message_id, second.id);
+    assert!(matches!(
+        conn.mark_read(standup.id, host.id, elsewhere.id, now),
+        Err(DieselError::NotFound)
+    ));
+
+    // Messages are ordered by timestamp first, so one stored late with an
+    // earlier timestamp is already behind the marker.
+    conn.create_message(NewMessage {
+        content: "late".to_string(),
+        sender_id: guest.id,
+        meeting_id: standup.id,
+        timestamp: first.timestamp,
+    })
+    .unwrap();
+    let unread = conn.unread_counts(host.id, &ids).unwrap();
+    assert_eq!(unread.get(&standup.id), Some(&0));
+    assert_eq!(unread.get(&retro.id), Some(&1));
+    let receipts = conn.list_read_receipts(standup.id).unwrap();
+    assert_eq!(receipts.len(), 1);
+    assert_eq!(receipts[0].user_id, host.id);
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn edits_and_deletes_keep_revisions_and_leave_a_tombstone() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let guest = user(&mut conn, "guest");
+    let standup = meeting(&mut conn, "Standup", host.id);
+    let retro = meeting(&mut conn, "Retro", host.id);
+    let original = message(&mut conn, "helo", guest.id, standup.id);
+    // Whole seconds, so the stored timestamps compare equal.
+    let now = NaiveDate::from_ymd_opt(2024, 9, 7)
+        .unwrap()
+        .and_hms_opt(10, 0, 0)
+        .unwrap();
+
+    let edited = conn
+        .edit_message(original.id, guest.id, "hello", now)
+        .unwrap();
+    assert_eq!(edited.content, "hello");
+    assert_eq!(edited.edited_at, Some(now));
+    assert_eq!(edited.timestamp, original.timestamp);
+    assert!(matches!(
+        conn.get_message(retro.id, original.id),
+        Err(DieselError::NotFound)
+    ));
+
+    let unread = conn.unread_counts(host.id, &[standup.id]).unwrap();
+    assert_eq!(unread.get(&standup.id), Some(&1));
+    let deleted = conn.delete_message(original.id, host.id, now).unwrap();
+    assert_eq!(deleted.content, "");
+    assert_eq!(deleted.deleted_at, Some(now));
+    assert_eq!(newest_messages(&mut conn, standup.id).len(), 1);
+    let unread = conn.unread_counts(host.id, &[standup.id]).unwrap();
+    assert_eq!(unread.get(&standup.id), Some(&0));
+
+    // A tombstone can be neither edited nor deleted again.
+    assert!(matches!(
+        conn.edit_message(original.id, guest.id, "again", now),
+        Err(DieselError::NotFound)
+    ));
+    assert!(matches!(
+        conn.delete_message(original.id, host.id, now),
+        Err(DieselError::NotFound)
+    ));
+
+    let revisions = conn.list_message_revisions(original.id).unwrap();
+    let history: Vec<_> = revisions
+        .iter()
+        .map(|r| (r.content.as_str(), r.action, r.changed_by))
+        .collect();
+    assert_eq!(
+        history,
+        [
+            ("helo", RevisionAction::Edit, guest.id),
+            ("hello", RevisionAction::Delete, host.id),
+        ]
+    );
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn message_pages_walk_both_ways_in_timestamp_then_id_order() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let standup = meeting(&mut conn, "Standup", host.id);
+    let at = NaiveDate::from_ymd_opt(2024, 9, 7)
+        .unwrap()
+        .and_hms_opt(10, 0, 0)
+        .unwrap();
+    // "b" and "c" share a timestamp, and "a" was stored last but is oldest.
+    for (content, minutes) in [("d", 3), ("b", 1), ("c", 1), ("e", 4), ("a", 0)] {
+        conn.create_message(NewMessage {
+            content: content.to_string(),
+            sender_id: host.id,
+            meeting_id: standup.id,
+            timestamp: at + chrono::Duration::minutes(minutes),
+        })
+        .unwrap();
+    }
+    let contents = |page: &MessagePage| -> Vec<String> {
+        page.messages.iter().map(|m| m.content.clone()).collect()
+    };
+
+    let newest = conn
+        .get_message_page(standup.id, &page(PageDirection::Before, None, 2))
+        .unwrap();
+    assert_eq!(contents(&newest), ["d", "e"]);
+    let older = conn
+        .get_message_page(
+            standup.id,
+            &page(PageDirection::Before, newest.next_cursor.as_deref(), 2),
+        )
+        .unwrap();
+    assert_eq!(contents(&older), ["b", "c"]);
+    let oldest = conn
+        .get_message_page(
+            standup.id,
+            &page(PageDirection::Before, older.next_cursor.as_deref(), 2),
+        )
+        .unwrap();
+    assert_eq!(contents(&oldest), ["a"]);
+    assert_eq!(oldest.next_cursor, None);
+
+    let first = conn
+        .get_message_page(standup.id, &page(PageDirection::After, None, 3))
+        .unwrap();
+    assert_eq!(contents(&first), ["a", "b", "c"]);
+    let rest = conn
+        .get_message_page(
+            standup.id,
+            &page(PageDirection::After, first.next_cursor.as_deref(), 3),
+        )
+        .unwrap();
+    assert_eq!(contents(&rest), ["d", "e"]);
+    assert_eq!(rest.next_cursor, None);
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn list_meetings_applies_timeframe_creator_and_date_filters() {
+    let mut conn = test_connection();
+    let alice = user(&mut conn, "alice");
+    let bob = user(&mut conn, "bob");
+    let early = meeting_on(&mut conn, "Early", alice.id, 3);
+    let middle = meeting_on(&mut conn, "Middle", bob.id, 10);
+    let late = meeting_on(&mut conn, "Late", alice.id, 20);
+    let now = NaiveDate::from_ymd_opt(2024, 9, 15)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+
+    let ids = |meetings: Vec<Meeting>| meetings.iter().map(|m| m.id).collect::<Vec<_>>();
+    let mine = MeetingFilter {
+        creator_id: Some(alice.id),
+        ..Default::default()
+    };
+    assert_eq!(
+        ids(conn.list_meetings(&mine, now).unwrap()),
+        [early.id, late.id]
+    );
+
+    let upcoming = MeetingFilter {
+        when: Some(MeetingTimeframe::Upcoming),
+        creator_id: Some(alice.id),
+        ..Default::default()
+    };
+    assert_eq!(ids(conn.list_meetings(&upcoming, now).unwrap()), [late.id]);
+
+    let past_in_range = MeetingFilter {
+        when: Some(MeetingTimeframe::Past),
+        from: Some(middle.start_time),
+        to: Some(late.start_time),
+        ..Default::default()
+    };
+    assert_eq!(
+        ids(conn.list_meetings(&past_in_range, now).unwrap()),
+        [middle.id]
+    );
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn update_meeting_only_changes_given_fields() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let standup = meeting(&mut conn, "Standup", host.id);
+
+    let updated = conn
+        .update_meeting(
+            standup.id,
+            UpdateMeeting {
+                title: Some("Daily standup".to_string()),
+                description: Some(Some("Every morning".to_string())),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(updated.title, "Daily standup");
+    assert_eq!(updated.description.as_deref(), Some("Every morning"));
+    assert_eq!(updated.start_time, standup.start_time);
+
+    let cleared = conn
+        .update_meeting(
+            standup.id,
+            UpdateMeeting {
+                description: Some(None),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(cleared.title, "Daily standup");
+    assert_eq!(cleared.description, None);
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn delete_meeting_removes_its_messages() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let standup = meeting(&mut conn, "Standup", host.id);
+    let retro = meeting(&mut conn, "Retro", host.id);
+    message(&mut conn, "bye", host.id, standup.id);
+    message(&mut conn, "stays", host.id, retro.id);
+
+    assert_eq!(conn.delete_meeting(standup.id).unwrap(), 1);
+    assert!(matches!(
+        conn.get_meeting_by_id(standup.id),
+        Err(DieselError::NotFound)
+    ));
+    assert!(newest_messages(&mut conn, standup.id).is_empty());
+    assert_eq!(newest_messages(&mut conn, retro.id).len(), 1);
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn create_meeting_makes_the_creator_its_host() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let standup = meeting(&mut conn, "Standup", host.id);
+
+    let participant = conn.get_participant(standup.id, host.id).unwrap();
+    assert_eq!(participant.role, ParticipantRole::Host);
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn participants_can_be_added_listed_and_removed() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let guest = user(&mut conn, "guest");
+    let standup = meeting(&mut conn, "Standup", host.id);
+
+    let added = conn
+        .add_participant(NewParticipant {
+            meeting_id: standup.id,
+            user_id: guest.id,
+            role: ParticipantRole::Viewer,
+        })
+        .unwrap();
+    assert_eq!(added.role, ParticipantRole::Viewer);
+
+    let listed: Vec<_> = conn
+        .list_participants(standup.id)
+        .unwrap()
+        .into_iter()
+        .map(|p| (p.id, p.name, p.role))
+        .collect();
+    assert_eq!(
+        listed,
+        [
+            (host.id, "host".to_string(), ParticipantRole::Host),
+            (guest.id, "guest".to_string(), ParticipantRole::Viewer),
+        ]
+    );
+
+    assert_eq!(conn.remove_participant(standup.id, guest.id).unwrap(), 1);
+    assert!(matches!(
+        conn.get_participant(standup.id, guest.id),
+        Err(DieselError::NotFound)
+    ));
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn add_participant_rejects_duplicates() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let standup = meeting(&mut conn, "Standup", host.id);
+
+    let duplicate = conn.add_participant(NewParticipant {
+        meeting_id: standup.id,
+        user_id: host.id,
+        role: ParticipantRole::Attendee,
+    });
+    assert!(matches!(
+        duplicate,
+        Err(DieselError::DatabaseError(
+            DatabaseErrorKind::UniqueViolation,
+            _
+        ))
+    ));
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn pending_invitations_match_user_id_or_email() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let guest = user(&mut conn, "guest");
+    let standup = meeting(&mut conn, "Standup", host.id);
+    let invite = |conn: &mut PgConnection, user_id, email: Option<&str>| {
+        conn.create_invitation(NewInvitation {
+            meeting_id: standup.id,
+            inviter_id: host.id,
+            invitee_user_id: user_id,
+            invitee_email: email.map(str::to_string),
+            role: ParticipantRole::Attendee,
+        })
+        .unwrap()
+    };
+    let by_id = invite(&mut conn, Some(guest.id), None);
+    let by_email = invite(&mut conn, None, Some("Guest@Example.com"));
+    invite(&mut conn, None, Some("someone@example.com"));
+
+    let mut ids: Vec<_> = conn
+        .list_pending_invitations(&guest)
+        .unwrap()
+        .iter()
+        .map(|i| i.id)
+        .collect();
+    ids.sort();
+    assert_eq!(ids, [by_id.id, by_email.id]);
+    assert!(by_email.is_addressed_to(&guest));
+
+    conn.decline_invitation(by_id.id).unwrap();
+    let remaining = conn.list_pending_invitations(&guest).unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].id, by_email.id);
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn accept_invitation_adds_the_participant_once() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let guest = user(&mut conn, "guest");
+    let standup = meeting(&mut conn, "Standup", host.id);
+    let invitation = conn
+        .create_invitation(NewInvitation {
+            meeting_id: standup.id,
+            inviter_id: host.id,
+            invitee_user_id: Some(guest.id),
+            invitee_email: None,
+            role: ParticipantRole::CoHost,
+        })
+        .unwrap();
+
+    let participant = conn.accept_invitation(invitation.id, guest.id).unwrap();
+    assert_eq!(participant.role, ParticipantRole::CoHost);
+    assert_eq!(
+        conn.get_invitation(invitation.id).unwrap().status,
+        InvitationStatus::Accepted
+    );
+    assert!(matches!(
+        conn.accept_invitation(invitation.id, guest.id),
+        Err(DieselError::NotFound)
+    ));
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn redeem_join_link_enforces_max_uses_expiry_and_revocation() {
+    let mut conn = test_connection();
+    let host = user(&mut conn, "host");
+    let first = user(&mut conn, "first");
+    let second = user(&mut conn, "second");
+    let standup = meeting(&mut conn, "Standup", host.id);
+    let now = chrono::Utc::now().naive_utc();
+    let link = |conn: &mut PgConnection, max_uses| {
+        conn.create_join_link(NewJoinLink {
+            meeting_id: standup.id,
+            created_by: host.id,
+            role: ParticipantRole::Viewer,
+            max_uses,
+            passcode_hash: None,
+            expires_at: now + chrono::Duration::hours(1),
+        })
+        .unwrap()
+    };
+
+    let single_use = link(&mut conn, Some(1));
+    let joined = conn
+        .redeem_join_link(single_use.id, first.id, now)
+        .unwrap()
+        .expect("first use succeeds");
+    assert_eq!(joined.role, ParticipantRole::Viewer);
+    assert!(conn
+        .redeem_join_link(single_use.id, second.id, now)
+        .unwrap()
+        .is_none());
+    assert_eq!(conn.get_join_link(single_use.id).unwrap().uses, 1);
+
+    let unlimited = link(&mut conn, None);
+    let later = now + chrono::Duration::hours(2);
+    assert!(conn
+        .redeem_join_link(unlimited.id, second.id, later)
+        .unwrap()
+        .is_none());
+    conn.revoke_join_link(unlimited.id, now).unwrap();
+    assert!(conn
+        .redeem_join_link(unlimited.id, second.id, now)
+        .unwrap()
+        .is_none());
+    assert!(matches!(
+        conn.get_participant(standup.id, second.id),
+        Err(DieselError::NotFound)
+    ));
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn rotate_refresh_token_replaces_the_token_and_detects_reuse() {
+    let mut conn = test_connection();
+    let alice = user(&mut conn, "alice");
+    let now = chrono::Utc::now().naive_utc();
+    let expires_at = now + chrono::Duration::days(1);
+    let session = conn
+        .create_session(alice.id, "first".to_string(), expires_at)
+        .unwrap();
+
+    let rotated = conn
+        .rotate_refresh_token("first", "second".to_string(), expires_at, now)
+        .unwrap();
+    assert!(matches!(rotated, RefreshOutcome::Rotated(s) if s.id == session.id));
+
+    let replayed = conn
+        .rotate_refresh_token("first", "third".to_string(), expires_at, now)
+        .unwrap();
+    assert!(matches!(replayed, RefreshOutcome::Reused(s) if s.id == session.id));
+    assert!(conn.get_session(session.id).unwrap().revoked_at.is_some());
+
+    let after_revocation = conn
+        .rotate_refresh_token("second", "fourth".to_string(), expires_at, now)
+        .unwrap();
+    assert!(matches!(after_revocation, RefreshOutcome::Revoked));
+    assert!(matches!(
+        conn.rotate_refresh_token("unknown", "fifth".to_string(), expires_at, now)
+            .unwrap(),
+        RefreshOutcome::Unknown
+    ));
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn expired_refresh_tokens_are_not_rotated() {
+    let mut conn = test_connection();
+    let alice = user(&mut conn, "alice");
+    let now = chrono::Utc::now().naive_utc();
+    conn.create_session(
+        alice.id,
+        "old".to_string(),
+        now - chrono::Duration::minutes(1),
+    )
+    .unwrap();
+
+    assert!(matches!(
+        conn.rotate_refresh_token("old", "new".to_string(), now, now)
+            .unwrap(),
+        RefreshOutcome::Expired
+    ));
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn revoke_user_sessions_only_touches_that_user() {
+    let mut conn = test_connection();
+    let alice = user(&mut conn, "alice");
+    let bob = user(&mut conn, "bob");
+    let now = chrono::Utc::now().naive_utc();
+    let expires_at = now + chrono::Duration::days(1);
+    let laptop = conn
+        .create_session(alice.id, "laptop".to_string(), expires_at)
+        .unwrap();
+    let phone = conn
+        .create_session(alice.id, "phone".to_string(), expires_at)
+        .unwrap();
+    let other = conn
+        .create_session(bob.id, "other".to_string(), expires_at)
+        .unwrap();
+
+    assert_eq!(conn.revoke_session(laptop.id, now).unwrap(), 1);
+    assert_eq!(conn.revoke_user_sessions(alice.id, now).unwrap(), 1);
+    assert!(conn.get_session(phone.id).unwrap().revoked_at.is_some());
+    assert!(conn.get_session(other.id).unwrap().revoked_at.is_none());
+}